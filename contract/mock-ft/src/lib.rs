@@ -0,0 +1,59 @@
+/*!
+Minimal NEP-141 fungible token for exercising the marketplace's token payments locally.
+NOTES:
+  - Anyone can mint to any account, so this must never be deployed outside a test network.
+  - Accounts still have to be registered with `storage_deposit` before they can receive tokens,
+    including the marketplace account.
+*/
+use near_contract_standards::fungible_token::metadata::{
+    FungibleTokenMetadata, FungibleTokenMetadataProvider, FT_METADATA_SPEC,
+};
+use near_contract_standards::fungible_token::FungibleToken;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::{near_bindgen, AccountId, PanicOnDefault, PromiseOrValue};
+
+
+#[near_bindgen]
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+pub struct Contract {
+    token: FungibleToken,
+    symbol: String,
+    decimals: u8,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Initializes an empty token with the given symbol and decimals.
+    #[init]
+    pub fn new(symbol: String, decimals: u8) -> Self {
+        Self {
+            token: FungibleToken::new(b"a".to_vec()),
+            symbol,
+            decimals,
+        }
+    }
+
+    /// Mints `amount` to a registered `account_id`.
+    pub fn mint(&mut self, account_id: AccountId, amount: U128) {
+        self.token.internal_deposit(&account_id, amount.0);
+    }
+}
+
+near_contract_standards::impl_fungible_token_core!(Contract, token);
+near_contract_standards::impl_fungible_token_storage!(Contract, token);
+
+#[near_bindgen]
+impl FungibleTokenMetadataProvider for Contract {
+    fn ft_metadata(&self) -> FungibleTokenMetadata {
+        FungibleTokenMetadata {
+            spec: FT_METADATA_SPEC.to_string(),
+            name: format!("Mock {}", self.symbol),
+            symbol: self.symbol.clone(),
+            icon: None,
+            reference: None,
+            reference_hash: None,
+            decimals: self.decimals,
+        }
+    }
+}
@@ -18,17 +18,41 @@ NOTES:
 use near_contract_standards::non_fungible_token::metadata::{
     NFTContractMetadata, NonFungibleTokenMetadataProvider, TokenMetadata, NFT_METADATA_SPEC,
 };
+use near_contract_standards::non_fungible_token::approval::NonFungibleTokenApproval;
 use near_contract_standards::non_fungible_token::NonFungibleToken;
 use near_contract_standards::non_fungible_token::{Token, TokenId};
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::collections::{LazyOption, LookupMap, UnorderedSet};
 use near_sdk::{
-    env, near_bindgen, require, AccountId, BorshStorageKey, PanicOnDefault, Promise, PromiseOrValue,
+    assert_one_yocto, env, ext_contract, near_bindgen, require, AccountId, Balance, BorshStorageKey, Gas, PanicOnDefault, Promise, PromiseOrValue,
 };
-use near_sdk::json_types::Base64VecU8;
+use near_sdk::json_types::{Base64VecU8, U128};
+use near_sdk::serde::{Deserialize, Serialize};
 use iso8601::datetime;
 use std::collections::HashMap;
 
+/// Royalty shares are expressed in basis points of the sale balance.
+pub const ROYALTY_TOTAL_BPS: u32 = 10000;
+/// Payout size used when the caller does not limit it.
+pub const MAX_LEN_PAYOUT: u32 = 10;
+/// Gas for telling a revoked account, such as the marketplace, that its approval is gone.
+pub const NFT_ON_REVOKE_GAS: Gas = Gas(10_000_000_000_000);
+
+
+/// Implemented by approved accounts that keep state per approval, e.g. a marketplace listing.
+#[ext_contract(ext_revoke_receiver)]
+pub trait NonFungibleTokenRevokeReceiver {
+    fn nft_on_revoke(&mut self, token_id: TokenId, owner_id: AccountId);
+}
+
+
+/// NEP-199 payout: the amount owed to each account from a sale of the token.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Payout {
+    pub payout: HashMap<AccountId, U128>,
+}
+
 
 #[derive(Default, BorshDeserialize, BorshSerialize)]
 pub struct TokenCounter {
@@ -54,12 +78,21 @@ impl TokenCounter {
 pub struct RietsToken {
     tokens: NonFungibleToken,
     metadata: LazyOption<NFTContractMetadata>,
-    token_counter: TokenCounter
+    token_counter: TokenCounter,
+    royalties: LookupMap<TokenId, HashMap<AccountId, u32>>
     
 }
 
 // const DATA_IMAGE_SVG_NEAR_ICON: &str = "data:image/svg+xml,%3Csvg xmlns='http://www.w3.org/2000/svg' viewBox='0 0 288 288'%3E%3Cg id='l' data-name='l'%3E%3Cpath d='M187.58,79.81l-30.1,44.69a3.2,3.2,0,0,0,4.75,4.2L191.86,103a1.2,1.2,0,0,1,2,.91v80.46a1.2,1.2,0,0,1-2.12.77L102.18,77.93A15.35,15.35,0,0,0,90.47,72.5H87.34A15.34,15.34,0,0,0,72,87.84V201.16A15.34,15.34,0,0,0,87.34,216.5h0a15.35,15.35,0,0,0,13.08-7.31l30.1-44.69a3.2,3.2,0,0,0-4.75-4.2L96.14,186a1.2,1.2,0,0,1-2-.91V104.61a1.2,1.2,0,0,1,2.12-.77l89.55,107.23a15.35,15.35,0,0,0,11.71,5.43h3.13A15.34,15.34,0,0,0,216,201.16V87.84A15.34,15.34,0,0,0,200.66,72.5h0A15.35,15.35,0,0,0,187.58,79.81Z'/%3E%3C/g%3E%3C/svg%3E";
 
+/// State written before tokens stored royalties, read once by `migrate`.
+#[derive(BorshDeserialize, BorshSerialize)]
+struct RietsTokenWithoutRoyalties {
+    tokens: NonFungibleToken,
+    metadata: LazyOption<NFTContractMetadata>,
+    token_counter: TokenCounter
+}
+
 #[derive(BorshSerialize, BorshStorageKey)]
 enum StorageKey {
     NonFungibleToken,
@@ -67,6 +100,7 @@ enum StorageKey {
     TokenMetadata,
     Enumeration,
     Approval,
+    Royalties,
 }
 
 #[near_bindgen]
@@ -94,7 +128,23 @@ impl RietsToken {
                 Some(StorageKey::Approval),
             ),
             metadata: LazyOption::new(StorageKey::Metadata, Some(&metadata)),
-            token_counter: TokenCounter::new()
+            token_counter: TokenCounter::new(),
+            royalties: LookupMap::new(StorageKey::Royalties)
+        }
+    }
+
+    /// Upgrades a deployed token contract to the layout with royalties. Tokens minted before the
+    /// upgrade carry no royalty, so their owner is paid the whole balance on resale.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let old: RietsTokenWithoutRoyalties = env::state_read().unwrap_or_else(|| env::panic_str("No state to migrate"));
+
+        Self {
+            tokens: old.tokens,
+            metadata: old.metadata,
+            token_counter: old.token_counter,
+            royalties: LookupMap::new(StorageKey::Royalties)
         }
     }
 
@@ -106,6 +156,9 @@ impl RietsToken {
     ///
     /// `self.tokens.mint` will enforce `predecessor_account_id` to equal the `owner_id` given in
     /// initialization call to `new`.
+    ///
+    /// `royalty` maps each royalty receiver, e.g. the property issuer or manager, to its share of
+    /// every resale in basis points. The token owner at sale time receives the remainder.
     #[payable]
     pub fn nft_mint(
         &mut self,
@@ -114,6 +167,7 @@ impl RietsToken {
         split_identifier: String,
         doc_url: String,
         image_url: String,
+        royalty: Option<HashMap<AccountId, u32>>,
     ) -> Token {
         let title = format!("Property {property_identifier} Unit {split_identifier}");
         let description = format!("Token for split {split_identifier} of property {property_identifier}");
@@ -133,9 +187,102 @@ impl RietsToken {
         };
         let token_id = self.token_counter.increment().to_string();
         assert_eq!(env::predecessor_account_id(), self.tokens.owner_id, "Unauthorized");
+
+        if let Some(royalty) = royalty {
+            require!(royalty.len() < MAX_LEN_PAYOUT as usize, "Too many royalty receivers");
+            require!(royalty.values().sum::<u32>() <= ROYALTY_TOTAL_BPS, "Royalties cannot exceed 10000 basis points");
+
+            if !royalty.is_empty() {
+                self.royalties.insert(&token_id, &royalty);
+            }
+        }
+
         self.tokens.internal_mint(token_id, token_owner_id.clone(), Some(token_metadata))
     }
 
+    /// NEP-199: how `balance` from a sale of `token_id` would be split between the royalty
+    /// receivers and the current owner.
+    pub fn nft_payout(&self, token_id: TokenId, balance: U128, max_len_payout: Option<u32>) -> Payout {
+        let owner_id = self.tokens.owner_by_id.get(&token_id).unwrap_or_else(|| env::panic_str("Token not found"));
+
+        self.internal_payout(&token_id, &owner_id, balance.0, max_len_payout)
+    }
+
+    /// NEP-199: transfer the token and return the payout the caller must honour for `balance`.
+    #[payable]
+    pub fn nft_transfer_payout(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: TokenId,
+        approval_id: Option<u64>,
+        memo: Option<String>,
+        balance: U128,
+        max_len_payout: Option<u32>,
+    ) -> Payout {
+        assert_one_yocto();
+
+        let sender = env::predecessor_account_id();
+        let (previous_owner, _) = self.tokens.internal_transfer(&sender, &receiver_id, &token_id, approval_id, memo);
+
+        self.internal_payout(&token_id, &previous_owner, balance.0, max_len_payout)
+    }
+
+    /// Transfers several tokens to one receiver in a single call, so either all of them move or
+    /// none do. `balance` is the price of the whole lot; the returned payout merges every token's
+    /// share of it.
+    #[payable]
+    pub fn nft_batch_transfer_payout(
+        &mut self,
+        receiver_id: AccountId,
+        token_ids: Vec<TokenId>,
+        memo: Option<String>,
+        balance: U128,
+        max_len_payout: Option<u32>,
+    ) -> Payout {
+        assert_one_yocto();
+        require!(!token_ids.is_empty(), "No tokens to transfer");
+
+        let sender = env::predecessor_account_id();
+        let count = token_ids.len() as u128;
+        let mut payout: HashMap<AccountId, U128> = HashMap::new();
+
+        for (index, token_id) in token_ids.iter().enumerate() {
+            let (previous_owner, _) = self.tokens.internal_transfer(&sender, &receiver_id, token_id, None, memo.clone());
+
+            // the first token carries the remainder of an uneven split
+            let token_balance = if index == 0 { balance.0 / count + balance.0 % count } else { balance.0 / count };
+
+            for (account_id, amount) in self.internal_payout(token_id, &previous_owner, token_balance, max_len_payout).payout {
+                let total = payout.get(&account_id).map(|total| total.0).unwrap_or(0);
+                payout.insert(account_id, U128::from(total + amount.0));
+            }
+        }
+
+        require!(payout.len() <= max_len_payout.unwrap_or(MAX_LEN_PAYOUT) as usize, "Payout exceeds max_len_payout");
+
+        Payout { payout }
+    }
+
+    fn internal_payout(&self, token_id: &TokenId, owner_id: &AccountId, balance: Balance, max_len_payout: Option<u32>) -> Payout {
+        let royalty = self.royalties.get(token_id).unwrap_or_default();
+
+        require!(royalty.len() < max_len_payout.unwrap_or(MAX_LEN_PAYOUT) as usize, "Payout exceeds max_len_payout");
+
+        let mut payout = HashMap::new();
+        let mut royalties_paid = 0;
+
+        // an owner who is also a royalty receiver is paid through the remainder
+        for (account_id, share) in royalty.iter().filter(|(account_id, _)| *account_id != owner_id) {
+            let amount = balance * u128::from(*share) / u128::from(ROYALTY_TOTAL_BPS);
+            royalties_paid += amount;
+            payout.insert(account_id.clone(), U128::from(amount));
+        }
+
+        payout.insert(owner_id.clone(), U128::from(balance - royalties_paid));
+
+        Payout { payout }
+    }
+
     pub fn get_user_properties(&self, account_id: AccountId) -> Vec<(TokenId, TokenMetadata)> {
 
         // let tokens = &mut self.tokens.tokens_per_owner;
@@ -185,32 +332,6 @@ impl RietsToken {
 
     }
 
-    pub fn approve_token_spender(&mut self, spender: AccountId, token_id: TokenId) {
-        let owner = self.tokens.owner_by_id.get(&token_id).unwrap();
-
-        require!(owner == env::signer_account_id(), "NFT Approve: Unauthorized");
-
-        if let Some(next_approval_id_by_id) = &mut self.tokens.next_approval_id_by_id {
-
-            let approval_id = next_approval_id_by_id.get(&token_id).unwrap_or(1);
-
-            if let Some(approval_by_id) = &mut self.tokens.approvals_by_id {
-
-                let mut app_id = approval_by_id.get(&token_id).unwrap_or(HashMap::new());
-
-                app_id.insert(spender.clone(), approval_id.clone());
-
-                approval_by_id.insert(&token_id, &app_id);
-            }
-
-            let next_approval_id = approval_id + 1;
-
-            next_approval_id_by_id.insert(&token_id, &next_approval_id);
-
-        }
-        
-    }
-
     pub fn transfer_token(&mut self, token_id: TokenId, receiver: AccountId) {
 
         let mut approval_id = None;
@@ -232,9 +353,50 @@ impl RietsToken {
 }
 
 //near_contract_standards::impl_non_fungible_token_core!(RietsToken, tokens);
-near_contract_standards::impl_non_fungible_token_approval!(RietsToken, tokens);
 near_contract_standards::impl_non_fungible_token_enumeration!(RietsToken, tokens);
 
+/// NEP-178 approvals. Approving with a `msg` calls `nft_on_approve` on the approved account, which
+/// lets a marketplace list the token; revoking calls `nft_on_revoke` so the listing is removed too.
+#[near_bindgen]
+impl NonFungibleTokenApproval for RietsToken {
+    #[payable]
+    fn nft_approve(&mut self, token_id: TokenId, account_id: AccountId, msg: Option<String>) -> Option<Promise> {
+        self.tokens.nft_approve(token_id, account_id, msg)
+    }
+
+    #[payable]
+    fn nft_revoke(&mut self, token_id: TokenId, account_id: AccountId) {
+        let owner_id = self.tokens.owner_by_id.get(&token_id).unwrap_or_else(|| env::panic_str("Token not found"));
+        let approved = self.nft_is_approved(token_id.clone(), account_id.clone(), None);
+
+        self.tokens.nft_revoke(token_id.clone(), account_id.clone());
+
+        if approved {
+            ext_revoke_receiver::ext(account_id)
+                .with_static_gas(NFT_ON_REVOKE_GAS)
+                .nft_on_revoke(token_id, owner_id);
+        }
+    }
+
+    #[payable]
+    fn nft_revoke_all(&mut self, token_id: TokenId) {
+        let owner_id = self.tokens.owner_by_id.get(&token_id).unwrap_or_else(|| env::panic_str("Token not found"));
+        let approved = self.tokens.approvals_by_id.as_ref().and_then(|by_id| by_id.get(&token_id)).unwrap_or_default();
+
+        self.tokens.nft_revoke_all(token_id.clone());
+
+        for account_id in approved.into_keys() {
+            ext_revoke_receiver::ext(account_id)
+                .with_static_gas(NFT_ON_REVOKE_GAS)
+                .nft_on_revoke(token_id.clone(), owner_id.clone());
+        }
+    }
+
+    fn nft_is_approved(&self, token_id: TokenId, approved_account_id: AccountId, approval_id: Option<u64>) -> bool {
+        self.tokens.nft_is_approved(token_id, approved_account_id, approval_id)
+    }
+}
+
 #[near_bindgen]
 impl NonFungibleTokenMetadataProvider for RietsToken {
     fn nft_metadata(&self) -> NFTContractMetadata {
@@ -242,3 +404,166 @@ impl NonFungibleTokenMetadataProvider for RietsToken {
     }
 }
 
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    const TOKEN: &str = "token.testnet";
+    const MARKET: &str = "riets-africa.testnet";
+    const OWNER: &str = "alice.testnet";
+    const BUYER: &str = "bob.testnet";
+    const ISSUER: &str = "issuer.testnet";
+    const MANAGER: &str = "manager.testnet";
+
+    fn account(id: &str) -> AccountId {
+        id.parse().unwrap()
+    }
+
+    fn set_context(predecessor: &str, deposit: Balance) {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(account(TOKEN))
+            .predecessor_account_id(account(predecessor))
+            .attached_deposit(deposit)
+            .build());
+    }
+
+    fn royalty(shares: &[(&str, u32)]) -> Option<HashMap<AccountId, u32>> {
+        Some(shares.iter().map(|(receiver, share)| (account(receiver), *share)).collect())
+    }
+
+    // attaches enough to cover the token's storage
+    fn mint(contract: &mut RietsToken, royalty: Option<HashMap<AccountId, u32>>) -> TokenId {
+        set_context(MARKET, 10u128.pow(23));
+
+        contract.nft_mint(
+            &account(OWNER),
+            "LKG".to_string(),
+            "LKG0001".to_string(),
+            "https://example.com/1.pdf".to_string(),
+            "https://example.com/lekki.png".to_string(),
+            royalty
+        ).token_id
+    }
+
+    fn amounts(payout: Payout) -> HashMap<String, Balance> {
+        payout.payout.into_iter().map(|(account_id, amount)| (account_id.to_string(), amount.0)).collect()
+    }
+
+    #[test]
+    fn payout_pays_each_royalty_and_the_owner_the_rest() {
+        set_context(MARKET, 0);
+        let mut contract = RietsToken::new();
+        let token_id = mint(&mut contract, royalty(&[(ISSUER, 1000), (MANAGER, 250)]));
+
+        let payout = amounts(contract.nft_payout(token_id, U128(10_001), None));
+
+        assert_eq!(payout[ISSUER], 1_000);
+        assert_eq!(payout[MANAGER], 250);
+        // rounding is left with the owner, so the payout always adds up to the balance
+        assert_eq!(payout[OWNER], 8_751);
+    }
+
+    #[test]
+    fn owner_who_is_a_royalty_receiver_is_paid_once() {
+        set_context(MARKET, 0);
+        let mut contract = RietsToken::new();
+        let token_id = mint(&mut contract, royalty(&[(OWNER, 1000), (ISSUER, 500)]));
+
+        let payout = amounts(contract.nft_payout(token_id, U128(10_000), None));
+
+        assert_eq!(payout.len(), 2);
+        assert_eq!(payout[OWNER], 9_500);
+        assert_eq!(payout[ISSUER], 500);
+    }
+
+    #[test]
+    #[should_panic(expected = "Payout exceeds max_len_payout")]
+    fn payout_longer_than_max_len_payout_is_refused() {
+        set_context(MARKET, 0);
+        let mut contract = RietsToken::new();
+        let token_id = mint(&mut contract, royalty(&[(ISSUER, 1000), (MANAGER, 250)]));
+
+        contract.nft_payout(token_id, U128(10_000), Some(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "Royalties cannot exceed 10000 basis points")]
+    fn royalties_above_the_whole_balance_are_refused() {
+        set_context(MARKET, 0);
+        let mut contract = RietsToken::new();
+
+        mint(&mut contract, royalty(&[(ISSUER, 9000), (MANAGER, 1001)]));
+    }
+
+    #[test]
+    fn transfer_payout_moves_the_token_and_pays_the_previous_owner() {
+        set_context(MARKET, 0);
+        let mut contract = RietsToken::new();
+        let token_id = mint(&mut contract, royalty(&[(ISSUER, 1000)]));
+
+        set_context(OWNER, 1);
+        let payout = amounts(contract.nft_transfer_payout(account(BUYER), token_id.clone(), None, None, U128(10_000), None));
+
+        assert_eq!(payout[OWNER], 9_000);
+        assert_eq!(payout[ISSUER], 1_000);
+        assert_eq!(contract.get_token(token_id).owner_id, account(BUYER));
+    }
+
+    #[test]
+    fn batch_transfer_payout_merges_every_token_share() {
+        set_context(MARKET, 0);
+        let mut contract = RietsToken::new();
+        let first = mint(&mut contract, royalty(&[(ISSUER, 1000)]));
+        let second = mint(&mut contract, royalty(&[(ISSUER, 1000), (MANAGER, 500)]));
+
+        set_context(OWNER, 1);
+        let payout = amounts(contract.nft_batch_transfer_payout(account(BUYER), vec![first.clone(), second.clone()], None, U128(20_001), None));
+
+        // the first token carries the odd unit: 10,001 and 10,000
+        assert_eq!(payout[ISSUER], 1_000 + 1_000);
+        assert_eq!(payout[MANAGER], 500);
+        assert_eq!(payout[OWNER], 9_001 + 8_500);
+        assert_eq!(payout.values().sum::<Balance>(), 20_001);
+
+        assert_eq!(contract.get_token(first).owner_id, account(BUYER));
+        assert_eq!(contract.get_token(second).owner_id, account(BUYER));
+    }
+
+    #[test]
+    #[should_panic(expected = "Payout exceeds max_len_payout")]
+    fn batch_payout_longer_than_max_len_payout_is_refused() {
+        set_context(MARKET, 0);
+        let mut contract = RietsToken::new();
+        let first = mint(&mut contract, royalty(&[(ISSUER, 1000)]));
+        let second = mint(&mut contract, royalty(&[(MANAGER, 500)]));
+
+        set_context(OWNER, 1);
+        contract.nft_batch_transfer_payout(account(BUYER), vec![first, second], None, U128(20_000), Some(2));
+    }
+
+    #[test]
+    fn migrate_keeps_tokens_minted_before_royalties() {
+        set_context(MARKET, 0);
+        let mut contract = RietsToken::new();
+        let token_id = mint(&mut contract, None);
+
+        env::state_write(&RietsTokenWithoutRoyalties {
+            tokens: contract.tokens,
+            metadata: contract.metadata,
+            token_counter: contract.token_counter
+        });
+
+        set_context(TOKEN, 0);
+        let mut contract = RietsToken::migrate();
+
+        assert_eq!(amounts(contract.nft_payout(token_id, U128(10_000), None))[OWNER], 10_000);
+
+        let token_id = mint(&mut contract, royalty(&[(ISSUER, 1000)]));
+        assert_eq!(token_id, "2");
+        assert_eq!(amounts(contract.nft_payout(token_id, U128(10_000), None))[ISSUER], 1_000);
+    }
+}
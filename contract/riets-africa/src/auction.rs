@@ -0,0 +1,439 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{Base64VecU8, U128};
+use near_sdk::serde::Serialize;
+use near_sdk::{env, log, near_bindgen, AccountId, Balance, Promise, require};
+
+use crate::{RietsAfrica, RietsAfricaExt};
+
+// bids in the last five minutes push the end of the auction back by the same window
+pub const DEFAULT_AUCTION_EXTENSION_MS: u64 = 5 * 60 * 1000;
+// share of an unrevealed sealed bid's deposit kept by the treasury, in basis points
+pub const UNREVEALED_BID_PENALTY_BPS: u128 = 1000;
+
+
+#[derive(Clone, PartialEq, BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub enum AuctionKind {
+    English,
+    Sealed
+}
+
+#[derive(Clone, PartialEq, BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub enum AuctionStatus {
+    Open,
+    Settling,
+    Settled,
+    Unsold,
+    Cancelled,
+    Failed
+}
+
+#[derive(Clone, BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SealedBid {
+    bidder: AccountId,
+    #[serde(skip)]
+    commitment: Vec<u8>,
+    deposit: Balance,
+    revealed_amount: Option<Balance>
+}
+
+#[derive(Clone, BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Auction {
+    id: U128,
+    kind: AuctionKind,
+    property_split_id: U128,
+    seller: AccountId,
+    reserve_price: Balance,
+    min_increment: Balance,
+    // block timestamps in milliseconds
+    ends_at: u64,
+    extension: u64,
+    // sealed auctions take bids until ends_at and reveals until reveal_ends_at
+    reveal_ends_at: Option<u64>,
+    highest_bid: Balance,
+    highest_bidder: Option<AccountId>,
+    status: AuctionStatus
+}
+
+impl Auction {
+    pub fn is_open(&self) -> bool {
+        self.status == AuctionStatus::Open && env::block_timestamp_ms() < self.ends_at
+    }
+
+    pub fn is_revealing(&self) -> bool {
+        let now = env::block_timestamp_ms();
+
+        match self.reveal_ends_at {
+            Some(reveal_ends_at) => self.status == AuctionStatus::Open && now >= self.ends_at && now < reveal_ends_at,
+            None => false
+        }
+    }
+
+    // the lowest bid the auction will take next
+    pub fn min_bid(&self) -> Balance {
+        match self.highest_bidder {
+            Some(_) => self.highest_bid + self.min_increment,
+            None => self.reserve_price
+        }
+    }
+}
+
+
+#[near_bindgen]
+impl RietsAfrica {
+
+    pub fn create_auction(&mut self, property_split_id: U128, reserve_price: U128, min_increment: U128, ends_at: u64, extension: Option<u64>) -> U128 {
+
+        let property_split = self.property_splits.get((property_split_id.0 - 1) as u64).unwrap_or_else(|| env::panic_str("Split id does not exist"));
+
+        let seller = env::signer_account_id();
+
+        require!(seller == self.get_split_seller(&property_split), "Not authorised to sell this property");
+        require!(!property_split.on_sale, "Split is listed for sale, delist it first");
+        require!(!self.is_split_locked(&property_split_id), "Split is already in an auction or bundle");
+        self.require_split_approved(&property_split_id);
+        require!(ends_at > env::block_timestamp_ms(), "Auction must end in the future");
+        require!(min_increment.0 > 0, "Minimum bid increment must be greater than zero");
+
+        let auction_id = U128::from(u128::from(self.auctions.len()) + 1);
+
+        let auction = Auction {
+            id: auction_id,
+            kind: AuctionKind::English,
+            property_split_id,
+            seller,
+            reserve_price: reserve_price.0,
+            min_increment: min_increment.0,
+            ends_at,
+            extension: extension.unwrap_or(DEFAULT_AUCTION_EXTENSION_MS),
+            reveal_ends_at: None,
+            highest_bid: 0,
+            highest_bidder: None,
+            status: AuctionStatus::Open
+        };
+
+        self.auctions.push(&auction);
+        self.active_auctions.insert(&property_split_id, &auction_id);
+
+        auction_id
+    }
+
+    pub fn create_sealed_auction(&mut self, property_split_id: U128, reserve_price: U128, commit_ends_at: u64, reveal_ends_at: u64) -> U128 {
+
+        let property_split = self.property_splits.get((property_split_id.0 - 1) as u64).unwrap_or_else(|| env::panic_str("Split id does not exist"));
+
+        let seller = env::signer_account_id();
+
+        require!(seller == self.get_split_seller(&property_split), "Not authorised to sell this property");
+        require!(!property_split.on_sale, "Split is listed for sale, delist it first");
+        require!(!self.is_split_locked(&property_split_id), "Split is already in an auction or bundle");
+        self.require_split_approved(&property_split_id);
+        require!(commit_ends_at > env::block_timestamp_ms(), "Bidding must end in the future");
+        require!(reveal_ends_at > commit_ends_at, "Reveal window must end after bidding closes");
+
+        let auction_id = U128::from(u128::from(self.auctions.len()) + 1);
+
+        let auction = Auction {
+            id: auction_id,
+            kind: AuctionKind::Sealed,
+            property_split_id,
+            seller,
+            reserve_price: reserve_price.0,
+            min_increment: 0,
+            ends_at: commit_ends_at,
+            extension: 0,
+            reveal_ends_at: Some(reveal_ends_at),
+            highest_bid: 0,
+            highest_bidder: None,
+            status: AuctionStatus::Open
+        };
+
+        self.auctions.push(&auction);
+        self.active_auctions.insert(&property_split_id, &auction_id);
+
+        auction_id
+    }
+
+    // `commitment` is the sha256 of `"{auction_id}:{bidder}:{amount}:{salt}"` with the amount in yoctoNEAR,
+    // so it cannot be replayed on another auction or by another bidder. The deposit must cover the amount
+    // and may exceed it to hide the bid
+    #[payable]
+    pub fn commit_sealed_bid(&mut self, auction_id: U128, commitment: Base64VecU8) {
+
+        let auction = self.get_auction(auction_id);
+
+        let bidder = env::signer_account_id();
+
+        require!(auction.kind == AuctionKind::Sealed, "Auction does not take sealed bids");
+        require!(auction.is_open(), "Auction is not open");
+        require!(bidder != auction.seller, "Not authorized");
+        require!(commitment.0.len() == 32, "Commitment must be a sha256 hash");
+        require!(env::attached_deposit() > 0, "Sealed bids need a deposit");
+
+        let mut bids = self.sealed_bids.get(&auction_id).unwrap_or_default();
+
+        require!(bids.iter().all(|bid| bid.bidder != bidder), "Bid already committed");
+
+        bids.push(SealedBid {
+            bidder,
+            commitment: commitment.0,
+            deposit: env::attached_deposit(),
+            revealed_amount: None
+        });
+
+        self.sealed_bids.insert(&auction_id, &bids);
+    }
+
+    pub fn reveal_sealed_bid(&mut self, auction_id: U128, amount: U128, salt: String) {
+
+        let mut auction = self.get_auction(auction_id);
+
+        let bidder = env::signer_account_id();
+
+        require!(auction.is_revealing(), "Auction is not in its reveal window");
+
+        let mut bids = self.sealed_bids.get(&auction_id).unwrap_or_default();
+        let bid = bids.iter_mut().find(|bid| bid.bidder == bidder).unwrap_or_else(|| env::panic_str("No bid to reveal"));
+
+        require!(bid.revealed_amount.is_none(), "Bid already revealed");
+        require!(env::sha256(format!("{}:{}:{}:{}", auction_id.0, bidder, amount.0, salt).as_bytes()) == bid.commitment, "Bid does not match its commitment");
+
+        bid.revealed_amount = Some(amount.0);
+
+        // bids under the reserve or above their own deposit are revealed but cannot win
+        if amount.0 >= auction.reserve_price && amount.0 <= bid.deposit && amount.0 > auction.highest_bid {
+            auction.highest_bid = amount.0;
+            auction.highest_bidder = Some(bidder);
+
+            self.auctions.replace((auction_id.0 - 1) as u64, &auction);
+        }
+
+        self.sealed_bids.insert(&auction_id, &bids);
+    }
+
+    #[payable]
+    pub fn place_bid(&mut self, auction_id: U128) {
+
+        let mut auction = self.get_auction(auction_id);
+
+        let bidder = env::signer_account_id();
+        let bid = env::attached_deposit();
+
+        require!(auction.kind == AuctionKind::English, "Auction takes sealed bids only");
+        require!(auction.is_open(), "Auction is not open");
+        require!(bidder != auction.seller, "Not authorized");
+        require!(bid >= auction.min_bid(), "Bid is below the minimum accepted bid");
+
+        if let Some(previous_bidder) = auction.highest_bidder.clone() {
+            Promise::new(previous_bidder.clone()).transfer(auction.highest_bid);
+
+            log!("Auction {} outbid, refunded {} to {}", auction_id.0, auction.highest_bid, previous_bidder);
+        }
+
+        auction.highest_bid = bid;
+        auction.highest_bidder = Some(bidder);
+
+        let now = env::block_timestamp_ms();
+        if auction.ends_at - now < auction.extension {
+            auction.ends_at = now + auction.extension;
+        }
+
+        self.auctions.replace((auction_id.0 - 1) as u64, &auction);
+    }
+
+    // the seller can call off an auction nobody has bid on
+    pub fn cancel_auction(&mut self, auction_id: U128) {
+
+        let mut auction = self.get_auction(auction_id);
+
+        require!(env::signer_account_id() == auction.seller, "Not authorised to cancel this auction");
+        require!(auction.status == AuctionStatus::Open, "Auction is not open");
+        require!(auction.highest_bidder.is_none(), "Auction already has bids");
+        require!(self.sealed_bids.get(&auction_id).is_none(), "Auction already has bids");
+
+        auction.status = AuctionStatus::Cancelled;
+
+        self.auctions.replace((auction_id.0 - 1) as u64, &auction);
+        self.active_auctions.remove(&auction.property_split_id);
+    }
+
+    // anyone can settle an auction once it has ended; the winner pays through the usual sale path
+    pub fn settle_auction(&mut self, auction_id: U128) {
+
+        let mut auction = self.get_auction(auction_id);
+
+        require!(auction.status == AuctionStatus::Open, "Auction is not open");
+        require!(env::block_timestamp_ms() >= auction.reveal_ends_at.unwrap_or(auction.ends_at), "Auction has not ended");
+
+        if auction.kind == AuctionKind::Sealed {
+            self.refund_sealed_bids(&auction);
+        }
+
+        let property_split = self.property_splits.get((auction.property_split_id.0 - 1) as u64).unwrap();
+
+        let winner = match auction.highest_bidder.clone() {
+            Some(winner) => winner,
+            None => {
+                auction.status = AuctionStatus::Unsold;
+                self.auctions.replace((auction_id.0 - 1) as u64, &auction);
+                self.active_auctions.remove(&auction.property_split_id);
+                return;
+            }
+        };
+
+        if auction.seller != self.get_split_seller(&property_split) {
+            // the split changed hands while the auction ran, so the winning bid cannot be honoured
+            auction.status = AuctionStatus::Failed;
+            self.auctions.replace((auction_id.0 - 1) as u64, &auction);
+            self.active_auctions.remove(&auction.property_split_id);
+
+            Promise::new(winner).transfer(auction.highest_bid);
+            return;
+        }
+
+        auction.status = AuctionStatus::Settling;
+        self.auctions.replace((auction_id.0 - 1) as u64, &auction);
+
+        self.transfer_split_on_sale(&property_split, winner, auction.highest_bid, auction.highest_bid, None, None);
+    }
+
+    pub fn get_auction(&self, auction_id: U128) -> Auction {
+        self.auctions.get((auction_id.0 - 1) as u64).unwrap_or_else(|| env::panic_str("Auction id does not exist"))
+    }
+
+    pub fn get_active_auctions(&self) -> Vec<Auction> {
+        self.auctions.iter().filter(|auction| auction.is_open()).collect()
+    }
+
+    // revealed sealed bids from everyone who did not win are refunded in full, as is the winner's
+    // deposit above the winning amount; unrevealed bids lose a penalty to the treasury
+    fn refund_sealed_bids(&mut self, auction: &Auction) {
+
+        let bids = self.sealed_bids.get(&auction.id).unwrap_or_default();
+
+        for bid in bids {
+            let refund = match bid.revealed_amount {
+                Some(amount) if Some(&bid.bidder) == auction.highest_bidder.as_ref() => bid.deposit - amount,
+                Some(_) => bid.deposit,
+                None => {
+                    let penalty = bid.deposit * UNREVEALED_BID_PENALTY_BPS / 10000;
+                    self.treasury += penalty;
+                    self.fees_collected += penalty;
+
+                    log!("Sealed bid from {} on auction {} was never revealed, {} forfeited", bid.bidder, auction.id.0, penalty);
+
+                    bid.deposit - penalty
+                }
+            };
+
+            if refund > 0 {
+                self.credit_refund(&bid.bidder, &None, refund);
+            }
+        }
+    }
+
+    // closes the auction being settled for a split once the token transfer has resolved
+    pub(crate) fn finish_auction(&mut self, property_split_id: &U128, transferred: bool) {
+
+        let auction_id = match self.active_auctions.get(property_split_id) {
+            Some(auction_id) => auction_id,
+            None => return
+        };
+
+        let mut auction = self.get_auction(auction_id);
+
+        if auction.status != AuctionStatus::Settling {
+            return;
+        }
+
+        auction.status = if transferred { AuctionStatus::Settled } else { AuctionStatus::Failed };
+
+        self.auctions.replace((auction_id.0 - 1) as u64, &auction);
+        self.active_auctions.remove(property_split_id);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{account, approve, function_calls, set_context_at, setup, OWNER};
+
+    const COMMIT_ENDS: u64 = 1_000;
+    const REVEAL_ENDS: u64 = 2_000;
+
+    fn sealed_auction() -> (RietsAfrica, U128) {
+        let mut contract = setup();
+
+        approve(&mut contract, "1", OWNER);
+
+        set_context_at(OWNER, 0, 0);
+        let auction_id = contract.create_sealed_auction(U128(1), U128(40_000), COMMIT_ENDS, REVEAL_ENDS);
+
+        (contract, auction_id)
+    }
+
+    fn commit(contract: &mut RietsAfrica, auction_id: U128, bidder: &str, amount: Balance, deposit: Balance) {
+        let commitment = env::sha256(format!("{}:{}:{}:{}", auction_id.0, bidder, amount, "salt").as_bytes());
+
+        set_context_at(bidder, deposit, 0);
+        contract.commit_sealed_bid(auction_id, Base64VecU8(commitment));
+    }
+
+    fn reveal(contract: &mut RietsAfrica, auction_id: U128, bidder: &str, amount: Balance, salt: &str) {
+        set_context_at(bidder, 0, COMMIT_ENDS);
+        contract.reveal_sealed_bid(auction_id, U128(amount), salt.to_string());
+    }
+
+    #[test]
+    fn highest_revealed_bid_wins() {
+        let (mut contract, auction_id) = sealed_auction();
+
+        commit(&mut contract, auction_id, "alice.testnet", 60_000, 80_000);
+        commit(&mut contract, auction_id, "bob.testnet", 55_000, 55_000);
+
+        reveal(&mut contract, auction_id, "alice.testnet", 60_000, "salt");
+        reveal(&mut contract, auction_id, "bob.testnet", 55_000, "salt");
+
+        let auction = contract.get_auction(auction_id);
+        assert_eq!(auction.highest_bid, 60_000);
+        assert_eq!(auction.highest_bidder, Some(account("alice.testnet")));
+    }
+
+    #[test]
+    #[should_panic(expected = "Bid does not match its commitment")]
+    fn reveal_with_the_wrong_salt_is_refused() {
+        let (mut contract, auction_id) = sealed_auction();
+
+        commit(&mut contract, auction_id, "alice.testnet", 60_000, 80_000);
+
+        reveal(&mut contract, auction_id, "alice.testnet", 60_000, "pepper");
+    }
+
+    #[test]
+    fn settlement_refunds_the_losers_the_winners_excess_and_penalises_unrevealed_bids() {
+        let (mut contract, auction_id) = sealed_auction();
+
+        commit(&mut contract, auction_id, "alice.testnet", 60_000, 80_000);
+        commit(&mut contract, auction_id, "bob.testnet", 55_000, 55_000);
+        commit(&mut contract, auction_id, "carol.testnet", 70_000, 70_000);
+
+        reveal(&mut contract, auction_id, "alice.testnet", 60_000, "salt");
+        reveal(&mut contract, auction_id, "bob.testnet", 55_000, "salt");
+
+        set_context_at("anyone.testnet", 0, REVEAL_ENDS);
+        contract.settle_auction(auction_id);
+
+        assert_eq!(function_calls(), vec!["nft_transfer_payout", "on_transfer_token_callback_on_sale"]);
+        assert!(contract.get_auction(auction_id).status == AuctionStatus::Settling);
+
+        assert_eq!(contract.get_claimable_refund(account("alice.testnet"), None).0, 20_000);
+        assert_eq!(contract.get_claimable_refund(account("bob.testnet"), None).0, 55_000);
+        assert_eq!(contract.get_claimable_refund(account("carol.testnet"), None).0, 63_000);
+        assert_eq!(contract.treasury, 7_000);
+        assert_eq!(contract.fees_collected, 7_000);
+    }
+}
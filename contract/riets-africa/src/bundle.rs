@@ -0,0 +1,297 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::{env, log, near_bindgen, AccountId, Balance, Gas, Promise, PromiseError, require};
+
+use crate::{ext_nft_contract, Payout, PropertySplit, RietsAfrica, RietsAfricaExt, SalePayment, SaleRecord};
+use crate::{MAX_FEE_BPS, MAX_LEN_PAYOUT, NFT_CONTRACT, SPLIT_SETTLE_GAS, XCC_GAS};
+
+
+#[derive(Clone, PartialEq, BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub enum BundleStatus {
+    Listed,
+    Settling,
+    Sold,
+    Delisted
+}
+
+#[derive(Clone, BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Bundle {
+    id: U128,
+    property_split_ids: Vec<U128>,
+    seller: AccountId,
+    price: Balance,
+    status: BundleStatus
+}
+
+// an even share of a bundle amount; the first split carries the remainder
+fn split_share(amount: Balance, index: usize, count: usize) -> Balance {
+    let count = count as u128;
+
+    if index == 0 { amount / count + amount % count } else { amount / count }
+}
+
+// the settling callback hands over every split in the bundle, so its gas grows with the bundle
+fn settle_gas(count: usize) -> Gas {
+    XCC_GAS + Gas(SPLIT_SETTLE_GAS.0 * count as u64)
+}
+
+
+#[near_bindgen]
+impl RietsAfrica {
+
+    // lists several splits owned by the caller as one lot at a single price
+    pub fn create_bundle(&mut self, property_split_ids: Vec<U128>, price: U128) -> U128 {
+
+        require!(property_split_ids.len() > 1, "A bundle needs at least two splits");
+        require!(property_split_ids.len() <= MAX_LEN_PAYOUT as usize, "Too many splits in one bundle");
+        require!(price.0 > 0, "Sale price must be greater than zero");
+
+        let seller = env::signer_account_id();
+        let bundle_id = U128::from(u128::from(self.bundles.len()) + 1);
+
+        for (index, property_split_id) in property_split_ids.iter().enumerate() {
+            let property_split = self.property_splits.get((property_split_id.0 - 1) as u64).unwrap_or_else(|| env::panic_str("Split id does not exist"));
+
+            require!(!property_split_ids[..index].contains(property_split_id), "Split listed more than once");
+            require!(seller == self.get_split_seller(&property_split), "Not authorised to sell this property");
+            require!(!property_split.on_sale, "Split is listed for sale, delist it first");
+            require!(!self.is_split_locked(property_split_id), "Split is already in an auction or bundle");
+            self.require_split_approved(property_split_id);
+
+            self.bundled_splits.insert(property_split_id, &bundle_id);
+        }
+
+        let bundle = Bundle {
+            id: bundle_id,
+            property_split_ids,
+            seller,
+            price: price.0,
+            status: BundleStatus::Listed
+        };
+
+        self.bundles.push(&bundle);
+
+        bundle_id
+    }
+
+    pub fn delist_bundle(&mut self, bundle_id: U128) {
+
+        let mut bundle = self.get_bundle(bundle_id);
+
+        require!(env::signer_account_id() == bundle.seller, "Not authorised to sell this property");
+        require!(bundle.status == BundleStatus::Listed, "Bundle is not listed");
+
+        bundle.status = BundleStatus::Delisted;
+
+        self.release_bundle(&bundle);
+    }
+
+    // buys every split in the bundle; the tokens move in one call so the buyer gets all or none
+    #[payable]
+    pub fn buy_bundle(&mut self, bundle_id: U128) {
+
+        let mut bundle = self.get_bundle(bundle_id);
+
+        let buyer = env::signer_account_id();
+
+        require!(bundle.status == BundleStatus::Listed, "Bundle is not listed");
+        require!(buyer != self.owner && buyer != bundle.seller, "Not authorized");
+        require!(env::attached_deposit() >= bundle.price, "Not sufficient deposit to buy bundle");
+
+        let splits = self.get_bundle_splits(&bundle);
+
+        require!(splits.iter().all(|split| self.get_split_seller(split) == bundle.seller), "Bundle is no longer valid");
+
+        bundle.status = BundleStatus::Settling;
+        self.bundles.replace((bundle_id.0 - 1) as u64, &bundle);
+
+        // bundles may span properties, so each split pays its own property's fee on its share of the price
+        let fee = splits.iter().enumerate()
+            .map(|(index, split)| split_share(bundle.price, index, splits.len()) * u128::from(self.get_property_fee(split.property_id)) / u128::from(MAX_FEE_BPS))
+            .sum::<u128>();
+
+        ext_nft_contract::ext(AccountId::new_unchecked(NFT_CONTRACT.to_string()))
+            .with_attached_deposit(1)
+            .nft_batch_transfer_payout(
+                buyer.clone(),
+                splits.iter().map(|split| split.token_id.clone()).collect(),
+                Some(format!("Sale of property split bundle {}", bundle_id.0)),
+                U128::from(bundle.price - fee),
+                Some(MAX_LEN_PAYOUT))
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(settle_gas(splits.len()))
+                    .on_bundle_transfer_callback(
+                        bundle_id,
+                        buyer,
+                        U128::from(fee),
+                        U128::from(env::attached_deposit()))
+            );
+    }
+
+    pub fn get_bundle(&self, bundle_id: U128) -> Bundle {
+        self.bundles.get((bundle_id.0 - 1) as u64).unwrap_or_else(|| env::panic_str("Bundle id does not exist"))
+    }
+
+    pub fn get_bundles_on_sale(&self) -> Vec<Bundle> {
+        self.bundles.iter().filter(|bundle| bundle.status == BundleStatus::Listed).collect()
+    }
+
+    fn get_bundle_splits(&self, bundle: &Bundle) -> Vec<PropertySplit> {
+        bundle.property_split_ids.iter()
+            .map(|split_id| self.property_splits.get((split_id.0 - 1) as u64).unwrap())
+            .collect()
+    }
+
+    // stores a bundle that has left the market and frees its splits
+    fn release_bundle(&mut self, bundle: &Bundle) {
+        for property_split_id in bundle.property_split_ids.iter() {
+            self.bundled_splits.remove(property_split_id);
+        }
+
+        self.bundles.replace((bundle.id.0 - 1) as u64, bundle);
+    }
+
+    #[private]
+    pub fn on_bundle_transfer_callback(
+        &mut self,
+        bundle_id: U128,
+        buyer: AccountId,
+        fee: U128,
+        deposit: U128,
+        #[callback_result] call_result: Result<Payout, PromiseError>
+    ) {
+
+        let mut bundle = self.get_bundle(bundle_id);
+
+        let reported_payout = match call_result {
+            Ok(payout) => payout.payout,
+            Err(_) => {
+                // none of the tokens moved, so the bundle goes back on sale and the buyer is refunded
+                bundle.status = BundleStatus::Listed;
+                self.bundles.replace((bundle_id.0 - 1) as u64, &bundle);
+
+                Promise::new(buyer.clone()).transfer(deposit.0);
+
+                log!("Transfer of bundle {} to {} failed, refunded {}", bundle_id.0, buyer, deposit.0);
+                return;
+            }
+        };
+
+        bundle.status = BundleStatus::Sold;
+        self.release_bundle(&bundle);
+
+        let payment = SalePayment {
+            seller: bundle.seller.clone(),
+            buyer: buyer.clone(),
+            currency: None,
+            price: U128::from(bundle.price),
+            fee,
+            deposit
+        };
+
+        let (royalties, refund) = self.pay_out_sale(&payment, reported_payout);
+
+        // the sale is recorded against every split, each carrying an even share of the amounts
+        let count = bundle.property_split_ids.len();
+        let share = |amount: Balance, index: usize| split_share(amount, index, count);
+
+        for (index, property_split_id) in bundle.property_split_ids.iter().enumerate() {
+            let sale_date = self.record_split_transfer(property_split_id, &buyer);
+
+            self.record_sale(&SaleRecord {
+                property_split_id: *property_split_id,
+                seller: bundle.seller.clone(),
+                buyer: buyer.clone(),
+                currency: None,
+                price: share(bundle.price, index),
+                fee: share(fee.0, index),
+                royalties: share(royalties, index),
+                refund: share(refund, index),
+                bundle_id: Some(bundle_id),
+                sale_date
+            });
+        }
+
+        log!("Bundle {} sold by {} to {} for {}", bundle_id.0, bundle.seller, buyer, bundle.price);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{account, approve, function_calls, minted_token, payout, set_context, setup, transfers, BUYER, MARKET, OWNER};
+    use crate::MAX_OPEN_OFFERS;
+
+    // the issuer's property grown to a full bundle of splits, each with as many open offers as it can hold
+    fn bundle_with_open_offers() -> (RietsAfrica, U128) {
+        let mut contract = setup();
+
+        set_context(MARKET, 0);
+        for token_id in 3..=MAX_LEN_PAYOUT {
+            contract.on_mint_nft_callback(U128(1), format!("LKG{:04}", token_id), None, U128(0), Ok(minted_token(&token_id.to_string(), OWNER)));
+        }
+
+        for split_id in 1..=u128::from(MAX_LEN_PAYOUT) {
+            for index in 0..MAX_OPEN_OFFERS {
+                set_context(&format!("bidder{}.testnet", index), 50_000);
+                contract.make_property_offer(U128(split_id), None);
+            }
+        }
+
+        for token_id in 1..=MAX_LEN_PAYOUT {
+            approve(&mut contract, &token_id.to_string(), OWNER);
+        }
+
+        set_context(OWNER, 0);
+        let bundle_id = contract.create_bundle((1..=u128::from(MAX_LEN_PAYOUT)).map(U128).collect(), U128(1_000_000));
+
+        (contract, bundle_id)
+    }
+
+    #[test]
+    fn settled_bundle_moves_every_split_and_pays_the_seller() {
+        let (mut contract, bundle_id) = bundle_with_open_offers();
+
+        set_context(BUYER, 1_000_000);
+        contract.buy_bundle(bundle_id);
+
+        assert_eq!(function_calls(), vec!["nft_batch_transfer_payout", "on_bundle_transfer_callback"]);
+
+        set_context(MARKET, 0);
+        contract.on_bundle_transfer_callback(bundle_id, account(BUYER), U128(25_000), U128(1_000_000), Ok(payout(&[(OWNER, 975_000)])));
+
+        assert!(env::used_gas() < settle_gas(MAX_LEN_PAYOUT as usize));
+
+        assert_eq!(transfers()[OWNER], 975_000);
+        assert_eq!(contract.treasury, 25_000);
+        assert!(contract.get_bundle(bundle_id).status == BundleStatus::Sold);
+
+        for split_id in 1..=u64::from(MAX_LEN_PAYOUT) {
+            assert_eq!(contract.property_splits.get(split_id - 1).unwrap().owner, account(BUYER));
+            assert_eq!(contract.get_split_sales(U128::from(u128::from(split_id)))[0].price, 100_000);
+        }
+
+        assert_eq!(contract.get_claimable_refund(account("bidder0.testnet"), None).0, 50_000 * u128::from(MAX_LEN_PAYOUT));
+    }
+
+    #[test]
+    fn failed_bundle_transfer_relists_the_bundle_and_refunds_the_buyer() {
+        let (mut contract, bundle_id) = bundle_with_open_offers();
+
+        set_context(BUYER, 1_000_000);
+        contract.buy_bundle(bundle_id);
+
+        set_context(MARKET, 0);
+        contract.on_bundle_transfer_callback(bundle_id, account(BUYER), U128(25_000), U128(1_000_000), Err(PromiseError::Failed));
+
+        assert_eq!(transfers()[BUYER], 1_000_000);
+        assert!(contract.get_bundle(bundle_id).status == BundleStatus::Listed);
+        assert_eq!(contract.property_splits.get(0).unwrap().owner, account(OWNER));
+        assert!(contract.get_split_sales(U128(1)).is_empty());
+    }
+}
@@ -0,0 +1,136 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::{env, near_bindgen, Balance, require};
+
+use crate::{is_issuer_sale_running, PropertySplit, RietsAfrica, RietsAfricaExt};
+
+
+#[derive(Clone, BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DutchAuction {
+    property_id: U128,
+    start_price: Balance,
+    floor_price: Balance,
+    // block timestamps in milliseconds; the price falls linearly between them
+    starts_at: u64,
+    ends_at: u64,
+    // splits whose transfer to a buyer has not resolved yet
+    pending_split_ids: Vec<U128>,
+    splits_sold: u64
+}
+
+impl DutchAuction {
+    pub fn current_price(&self) -> Balance {
+        let now = env::block_timestamp_ms();
+
+        if now <= self.starts_at {
+            self.start_price
+        } else if now >= self.ends_at {
+            self.floor_price
+        } else {
+            let elapsed = u128::from(now - self.starts_at);
+            let duration = u128::from(self.ends_at - self.starts_at);
+
+            self.start_price - (self.start_price - self.floor_price) * elapsed / duration
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        let now = env::block_timestamp_ms();
+        now >= self.starts_at && now < self.ends_at
+    }
+}
+
+
+#[near_bindgen]
+impl RietsAfrica {
+
+    pub fn start_dutch_auction(&mut self, property_id: U128, start_price: U128, floor_price: U128, starts_at: Option<u64>, ends_at: u64) {
+
+        require!(env::signer_account_id() == self.owner, "Not authorised");
+        self.properties.get((property_id.0 - 1) as u64).unwrap_or_else(|| env::panic_str("Property id does not exist"));
+
+        require!(!self.has_open_dutch_auction(&property_id), "Property already has a running Dutch auction");
+        require!(!self.has_open_primary_offering(&property_id), "Property already has a running primary offering");
+
+        let starts_at = starts_at.unwrap_or(env::block_timestamp_ms());
+
+        require!(ends_at > starts_at && ends_at > env::block_timestamp_ms(), "Auction must end after it starts and in the future");
+        require!(start_price.0 >= floor_price.0, "Start price cannot be below the floor price");
+
+        let auction = DutchAuction {
+            property_id,
+            start_price: start_price.0,
+            floor_price: floor_price.0,
+            starts_at,
+            ends_at,
+            pending_split_ids: Vec::new(),
+            splits_sold: 0
+        };
+
+        self.dutch_auctions.insert(&property_id, &auction);
+    }
+
+    // buys the next unsold split of the property at the current auction price
+    #[payable]
+    pub fn buy_from_dutch_auction(&mut self, property_id: U128) -> U128 {
+
+        let mut auction = self.dutch_auctions.get(&property_id).unwrap_or_else(|| env::panic_str("Property has no Dutch auction"));
+
+        let buyer = env::signer_account_id();
+
+        require!(buyer != self.owner, "Not authorized");
+        require!(auction.is_open(), "Dutch auction is not open");
+
+        let price = auction.current_price();
+
+        require!(env::attached_deposit() >= price, "Not sufficient deposit to buy property");
+
+        let property_split = self.get_issuer_splits(&property_id).into_iter().next()
+            .unwrap_or_else(|| env::panic_str("No splits left in this auction"));
+
+        self.reserve_split(&mut auction.pending_split_ids, &property_split.id);
+        self.dutch_auctions.insert(&property_id, &auction);
+
+        self.transfer_split_on_sale(&property_split, buyer, price, env::attached_deposit(), None, None);
+
+        property_split.id
+    }
+
+    pub fn get_dutch_auction(&self, property_id: U128) -> Option<DutchAuction> {
+        self.dutch_auctions.get(&property_id)
+    }
+
+    pub fn get_dutch_auction_price(&self, property_id: U128) -> U128 {
+        let auction = self.dutch_auctions.get(&property_id).unwrap_or_else(|| env::panic_str("Property has no Dutch auction"));
+
+        U128::from(auction.current_price())
+    }
+
+    pub(crate) fn has_open_dutch_auction(&self, property_id: &U128) -> bool {
+        match self.dutch_auctions.get(property_id) {
+            Some(auction) => is_issuer_sale_running(auction.ends_at, &auction.pending_split_ids),
+            None => false
+        }
+    }
+
+    // releases a split reserved by the property's Dutch auction once its transfer has resolved
+    pub(crate) fn finish_dutch_auction_sale(&mut self, property_split: &PropertySplit, transferred: bool) {
+
+        let mut auction = match self.dutch_auctions.get(&property_split.property_id) {
+            Some(auction) => auction,
+            None => return
+        };
+
+        if !self.release_split(&mut auction.pending_split_ids, &property_split.id) {
+            return;
+        }
+
+        if transferred {
+            auction.splits_sold += 1;
+        }
+
+        self.dutch_auctions.insert(&property_split.property_id, &auction);
+    }
+}
@@ -0,0 +1,666 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, log, near_bindgen, AccountId, Balance, Promise, require};
+
+use crate::valuation::require_currency_code;
+use crate::{PropertySplit, RietsAfrica, RietsAfricaExt, MAX_FEE_BPS};
+
+pub const MAX_PROPOSAL_OPTIONS: usize = 10;
+// in bytes
+pub const MAX_PROPOSAL_TITLE_LEN: usize = 200;
+pub const MAX_PROPOSAL_DESCRIPTION_LEN: usize = 2000;
+pub const MAX_PROPOSAL_OPTION_LEN: usize = 100;
+pub const DEFAULT_QUORUM_BPS: u16 = 2000;
+pub const DEFAULT_THRESHOLD_BPS: u16 = 5000;
+pub const DEFAULT_TIMELOCK_MS: u64 = 2 * 24 * 60 * 60 * 1000;
+
+
+#[derive(Clone, PartialEq, BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub enum ProposalStatus {
+    Active,
+    Passed,
+    Rejected,
+    QuorumNotMet
+}
+
+// what a passed proposal does to its property once the timelock is over
+#[derive(Clone, BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde", tag = "type", rename_all = "snake_case")]
+pub enum ProposalAction {
+    SetValuation { valuation: U128, currency: Option<String> },
+    SetPropertyManager { manager: Option<AccountId> },
+    ReleaseReserve { receiver: AccountId, amount: U128 },
+    // sells the whole property to `buyer`: every holder may tender their splits at `split_price` each
+    // for `duration_ms` after the proposal is executed
+    ApproveSale { buyer: AccountId, split_price: U128, duration_ms: u64 }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ProposalArgs {
+    pub property_id: U128,
+    pub title: String,
+    pub description: String,
+    pub options: Vec<String>,
+    pub starts_at: Option<u64>,
+    pub ends_at: u64,
+    pub action: Option<ProposalAction>
+}
+
+#[derive(Clone, BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SplitVote {
+    voter: AccountId,
+    option: u32,
+    // cast by the holder's delegate rather than the holder
+    delegated: bool
+}
+
+#[derive(Clone, PartialEq, BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Delegation {
+    delegator: AccountId,
+    // the property the delegation covers, or every property when not set
+    property_id: Option<U128>
+}
+
+#[derive(Clone, BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VotingPower {
+    own: u64,
+    delegated: u64,
+    total: u64
+}
+
+#[derive(Clone, BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Proposal {
+    id: U128,
+    property_id: U128,
+    proposer: AccountId,
+    title: String,
+    description: String,
+    options: Vec<String>,
+    // block timestamps in milliseconds
+    starts_at: u64,
+    ends_at: u64,
+    // rules in force when the proposal was created, in basis points of the property's splits
+    // and of the votes cast respectively
+    quorum_bps: u16,
+    threshold_bps: u16,
+    timelock_ms: u64,
+    total_splits: u64,
+    // one vote per split, counted per option
+    tallies: Vec<u64>,
+    votes_cast: u64,
+    status: ProposalStatus,
+    winning_option: Option<u32>,
+    finalized_at: Option<u64>,
+    // runs when the first option wins
+    action: Option<ProposalAction>,
+    executable_at: Option<u64>,
+    executed_at: Option<u64>
+}
+
+impl Proposal {
+    pub fn is_open(&self) -> bool {
+        let now = env::block_timestamp_ms();
+        self.status == ProposalStatus::Active && now >= self.starts_at && now < self.ends_at
+    }
+
+    // the option with the most votes, if no other option is tied with it
+    pub fn leading_option(&self) -> Option<u32> {
+        let most = *self.tallies.iter().max()?;
+
+        match self.tallies.iter().filter(|votes| **votes == most).count() {
+            1 => self.tallies.iter().position(|votes| *votes == most).map(|index| index as u32),
+            _ => None
+        }
+    }
+}
+
+
+// holds the caller to paying for the storage a call has added since `storage_before`, and sends back
+// whatever was attached above that
+fn refund_storage_deposit(storage_before: u64) {
+    let cost = env::storage_byte_cost() * Balance::from(env::storage_usage().saturating_sub(storage_before));
+    let deposit = env::attached_deposit();
+
+    require!(deposit >= cost, format!("Attach at least {} yoctoNEAR to cover storage", cost));
+
+    if deposit > cost {
+        Promise::new(env::signer_account_id()).transfer(deposit - cost);
+    }
+}
+
+
+#[near_bindgen]
+impl RietsAfrica {
+
+    pub fn set_governance_rules(&mut self, quorum_bps: u16, threshold_bps: u16, timelock_ms: u64) {
+        require!(env::signer_account_id() == self.owner, "Not authorised");
+        require!(quorum_bps <= MAX_FEE_BPS && threshold_bps <= MAX_FEE_BPS, "Rules cannot exceed 10000 basis points");
+
+        self.governance_quorum_bps = quorum_bps;
+        self.governance_threshold_bps = threshold_bps;
+        self.governance_timelock_ms = timelock_ms;
+    }
+
+    // any holder of the property's splits, or its manager, can put a question to the holders.
+    // An `action` is carried out by `execute_proposal` if the first option wins. The proposer pays for
+    // the storage the proposal takes, and gets back whatever is attached above that
+    #[payable]
+    pub fn create_proposal(&mut self, args: ProposalArgs) -> U128 {
+
+        let storage_before = env::storage_usage();
+
+        let ProposalArgs { property_id, title, description, options, starts_at, ends_at, action } = args;
+
+        let property = self.properties.get((property_id.0 - 1) as u64).unwrap_or_else(|| env::panic_str("Property id does not exist"));
+
+        let proposer = env::signer_account_id();
+
+        require!(
+            proposer == self.owner
+                || Some(proposer.clone()) == self.property_managers.get(&property_id)
+                || !self.get_voter_splits(&property_id, &proposer).is_empty(),
+            "Not authorised"
+        );
+        require!(!title.is_empty(), "Title cannot be empty");
+        require!(title.len() <= MAX_PROPOSAL_TITLE_LEN, "Title is too long");
+        require!(description.len() <= MAX_PROPOSAL_DESCRIPTION_LEN, "Description is too long");
+        require!(options.len() > 1 && options.len() <= MAX_PROPOSAL_OPTIONS, "A proposal needs between two and ten options");
+        require!(options.iter().all(|option| option.len() <= MAX_PROPOSAL_OPTION_LEN), "Option is too long");
+        require!(!property.split_ids.is_empty(), "Property has no splits to vote with");
+
+        let starts_at = starts_at.unwrap_or(env::block_timestamp_ms());
+
+        require!(ends_at > starts_at && ends_at > env::block_timestamp_ms(), "Voting must end after it starts and in the future");
+
+        match &action {
+            Some(ProposalAction::SetValuation { currency: Some(currency), .. }) => require_currency_code(currency),
+            Some(ProposalAction::ReleaseReserve { amount, .. }) => require!(amount.0 > 0, "Amount must be greater than zero"),
+            Some(ProposalAction::ApproveSale { split_price, duration_ms, .. }) => {
+                require!(split_price.0 > 0, "Sale price must be greater than zero");
+                require!(*duration_ms > 0, "Sale must stay open for some time");
+            }
+            _ => {}
+        }
+
+        let proposal_id = U128::from(u128::from(self.proposals.len()) + 1);
+
+        let proposal = Proposal {
+            id: proposal_id,
+            property_id,
+            proposer,
+            title,
+            description,
+            tallies: vec![0; options.len()],
+            options,
+            starts_at,
+            ends_at,
+            quorum_bps: self.governance_quorum_bps,
+            threshold_bps: self.governance_threshold_bps,
+            timelock_ms: self.governance_timelock_ms,
+            total_splits: property.split_ids.len() as u64,
+            votes_cast: 0,
+            status: ProposalStatus::Active,
+            winning_option: None,
+            finalized_at: None,
+            action,
+            executable_at: None,
+            executed_at: None
+        };
+
+        self.proposals.push(&proposal);
+
+        let mut property_proposals = self.property_proposals.get(&property_id).unwrap_or_default();
+        property_proposals.push(proposal_id);
+        self.property_proposals.insert(&property_id, &property_proposals);
+
+        refund_storage_deposit(storage_before);
+
+        proposal_id
+    }
+
+    // casts one vote for `option` with every split of the property the caller holds, and with the
+    // splits delegated to the caller that their holders have not voted themselves. Voting again
+    // moves those votes to the new option. The first vote of each split is stored, and paid for by the voter
+    #[payable]
+    pub fn vote(&mut self, proposal_id: U128, option: u32) -> u64 {
+
+        let storage_before = env::storage_usage();
+
+        let mut proposal = self.get_proposal(proposal_id);
+
+        let voter = env::signer_account_id();
+
+        require!(proposal.is_open(), "Voting is not open");
+        require!((option as usize) < proposal.options.len(), "Option does not exist");
+
+        let splits = self.get_voter_splits(&proposal.property_id, &voter);
+        let delegated_splits = self.get_delegated_splits(&proposal.property_id, &voter);
+
+        require!(!splits.is_empty() || !delegated_splits.is_empty(), "No splits of this property to vote with");
+
+        let mut votes = 0;
+
+        for property_split in splits.iter() {
+            self.cast_split_vote(&mut proposal, &property_split.id, &voter, option, false);
+            votes += 1;
+        }
+
+        for property_split in delegated_splits.iter() {
+            let direct_vote = self.split_votes.get(&(proposal_id, property_split.id)).map(|vote| !vote.delegated).unwrap_or(false);
+
+            if !direct_vote {
+                self.cast_split_vote(&mut proposal, &property_split.id, &voter, option, true);
+                votes += 1;
+            }
+        }
+
+        self.proposals.replace((proposal_id.0 - 1) as u64, &proposal);
+
+        refund_storage_deposit(storage_before);
+
+        log!("{} voted option {} on proposal {} with {} splits", voter, option, proposal_id.0, votes);
+
+        votes
+    }
+
+    // hands the caller's votes on one property, or on every property, to `delegate`; a delegation
+    // for a property takes precedence over one for every property. The delegator pays for its storage
+    #[payable]
+    pub fn delegate_votes(&mut self, delegate: AccountId, property_id: Option<U128>) {
+
+        let storage_before = env::storage_usage();
+
+        let delegator = env::signer_account_id();
+
+        require!(delegate != delegator, "Cannot delegate to yourself");
+
+        if let Some(property_id) = &property_id {
+            self.properties.get((property_id.0 - 1) as u64).unwrap_or_else(|| env::panic_str("Property id does not exist"));
+        }
+
+        if let Some(previous) = self.delegations.insert(&(delegator.clone(), property_id), &delegate) {
+            self.remove_delegator(&previous, &delegator, &property_id);
+        }
+
+        let mut delegators = self.delegators.get(&delegate).unwrap_or_default();
+        delegators.push(Delegation { delegator: delegator.clone(), property_id });
+        self.delegators.insert(&delegate, &delegators);
+
+        refund_storage_deposit(storage_before);
+
+        log!("{} delegated votes to {}", delegator, delegate);
+    }
+
+    // votes the delegate has already cast stay counted until the holder votes directly
+    pub fn revoke_delegation(&mut self, property_id: Option<U128>) {
+
+        let delegator = env::signer_account_id();
+
+        let delegate = self.delegations.remove(&(delegator.clone(), property_id)).unwrap_or_else(|| env::panic_str("No delegation to revoke"));
+
+        self.remove_delegator(&delegate, &delegator, &property_id);
+
+        log!("{} revoked delegation to {}", delegator, delegate);
+    }
+
+    pub fn get_delegate(&self, account_id: AccountId, property_id: Option<U128>) -> Option<AccountId> {
+        self.delegations.get(&(account_id, property_id))
+    }
+
+    pub fn get_delegators(&self, delegate: AccountId) -> Vec<Delegation> {
+        self.delegators.get(&delegate).unwrap_or_default()
+    }
+
+    // anyone can settle the result once voting has ended
+    pub fn finalize_proposal(&mut self, proposal_id: U128) -> ProposalStatus {
+
+        let mut proposal = self.get_proposal(proposal_id);
+
+        require!(proposal.status == ProposalStatus::Active, "Proposal is already finalized");
+        require!(env::block_timestamp_ms() >= proposal.ends_at, "Voting is still open");
+
+        let quorum = u128::from(proposal.total_splits) * u128::from(proposal.quorum_bps);
+
+        proposal.status = if u128::from(proposal.votes_cast) * u128::from(MAX_FEE_BPS) < quorum {
+            ProposalStatus::QuorumNotMet
+        } else {
+            match proposal.leading_option() {
+                Some(option) if u128::from(proposal.tallies[option as usize]) * u128::from(MAX_FEE_BPS) > u128::from(proposal.votes_cast) * u128::from(proposal.threshold_bps) => {
+                    proposal.winning_option = Some(option);
+                    ProposalStatus::Passed
+                }
+                _ => ProposalStatus::Rejected
+            }
+        };
+
+        proposal.finalized_at = Some(env::block_timestamp_ms());
+
+        if proposal.action.is_some() && proposal.winning_option == Some(0) {
+            proposal.executable_at = Some(env::block_timestamp_ms() + proposal.timelock_ms);
+        }
+
+        self.proposals.replace((proposal_id.0 - 1) as u64, &proposal);
+
+        proposal.status
+    }
+
+    // anyone can carry out a passed proposal's action once its timelock is over
+    pub fn execute_proposal(&mut self, proposal_id: U128) {
+
+        let mut proposal = self.get_proposal(proposal_id);
+
+        let executable_at = proposal.executable_at.unwrap_or_else(|| env::panic_str("Proposal has no action to execute"));
+
+        require!(proposal.executed_at.is_none(), "Proposal is already executed");
+        require!(env::block_timestamp_ms() >= executable_at, "Proposal is still timelocked");
+
+        proposal.executed_at = Some(env::block_timestamp_ms());
+        self.proposals.replace((proposal_id.0 - 1) as u64, &proposal);
+
+        let property_id = proposal.property_id;
+
+        match proposal.action.unwrap() {
+            ProposalAction::SetValuation { valuation, currency } => {
+                self.internal_set_property_valuation(&property_id, valuation.0, currency);
+            }
+            ProposalAction::SetPropertyManager { manager } => {
+                self.internal_set_property_manager(&property_id, manager);
+            }
+            ProposalAction::ReleaseReserve { receiver, amount } => {
+                self.internal_release_reserve(&property_id, receiver, amount.0);
+            }
+            ProposalAction::ApproveSale { buyer, split_price, duration_ms } => {
+                self.internal_open_property_sale(&property_id, proposal_id, buyer, split_price.0, duration_ms);
+            }
+        }
+
+        log!("Proposal {} executed on property {}", proposal_id.0, property_id.0);
+    }
+
+    pub fn get_proposal(&self, proposal_id: U128) -> Proposal {
+        self.proposals.get((proposal_id.0 - 1) as u64).unwrap_or_else(|| env::panic_str("Proposal id does not exist"))
+    }
+
+    pub fn get_property_proposals(&self, property_id: U128) -> Vec<Proposal> {
+        self.property_proposals.get(&property_id).unwrap_or_default().into_iter()
+            .map(|proposal_id| self.get_proposal(proposal_id))
+            .collect()
+    }
+
+    pub fn get_split_vote(&self, proposal_id: U128, property_split_id: U128) -> Option<SplitVote> {
+        self.split_votes.get(&(proposal_id, property_split_id))
+    }
+
+    // one vote per split of the property the account holds or has been delegated
+    pub fn get_voting_power(&self, property_id: U128, account_id: AccountId) -> VotingPower {
+        let own = self.get_voter_splits(&property_id, &account_id).len() as u64;
+        let delegated = self.get_delegated_splits(&property_id, &account_id).len() as u64;
+
+        VotingPower { own, delegated, total: own + delegated }
+    }
+
+    // records a split's vote, taking back whatever it had voted before on the proposal
+    fn cast_split_vote(&mut self, proposal: &mut Proposal, property_split_id: &U128, voter: &AccountId, option: u32, delegated: bool) {
+        let key = (proposal.id, *property_split_id);
+
+        match self.split_votes.get(&key) {
+            Some(previous) => proposal.tallies[previous.option as usize] -= 1,
+            None => proposal.votes_cast += 1
+        }
+
+        proposal.tallies[option as usize] += 1;
+
+        self.split_votes.insert(&key, &SplitVote { voter: voter.clone(), option, delegated });
+    }
+
+    fn remove_delegator(&mut self, delegate: &AccountId, delegator: &AccountId, property_id: &Option<U128>) {
+        let mut delegators = self.delegators.get(delegate).unwrap_or_default();
+
+        delegators.retain(|delegation| !(delegation.delegator == *delegator && delegation.property_id == *property_id));
+
+        if delegators.is_empty() {
+            self.delegators.remove(delegate);
+        } else {
+            self.delegators.insert(delegate, &delegators);
+        }
+    }
+
+    // the splits of the property held by accounts whose votes on it are delegated to `delegate`
+    fn get_delegated_splits(&self, property_id: &U128, delegate: &AccountId) -> Vec<PropertySplit> {
+        let mut delegators: Vec<AccountId> = Vec::new();
+
+        for delegation in self.delegators.get(delegate).unwrap_or_default() {
+            let effective_delegate = self.delegations.get(&(delegation.delegator.clone(), Some(*property_id)))
+                .or_else(|| self.delegations.get(&(delegation.delegator.clone(), None)));
+
+            if effective_delegate.as_ref() == Some(delegate) && !delegators.contains(&delegation.delegator) {
+                delegators.push(delegation.delegator);
+            }
+        }
+
+        delegators.iter()
+            .flat_map(|delegator| self.get_voter_splits(property_id, delegator))
+            .collect()
+    }
+
+    fn get_voter_splits(&self, property_id: &U128, account: &AccountId) -> Vec<PropertySplit> {
+        let property = self.properties.get((property_id.0 - 1) as u64).unwrap_or_else(|| env::panic_str("Property id does not exist"));
+
+        property.split_ids.iter()
+            .map(|split_id| self.property_splits.get((split_id.0 - 1) as u64).unwrap())
+            .filter(|property_split| property_split.owner == *account)
+            .collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{account, minted_token, payment, payout, set_context, set_context_at, setup, transfers, BUYER, MARKET, OWNER};
+
+    const ENDS: u64 = 1_000;
+    // covers the storage of a proposal or a vote, the rest is refunded
+    const STORAGE: Balance = 10_000_000_000_000_000_000_000;
+    const CAROL: &str = "carol.testnet";
+
+    // the setup property with a third split, its splits held by the owner, the buyer and carol, and
+    // a proposal put to them under the given rules
+    fn three_holders(quorum_bps: u16, threshold_bps: u16) -> (RietsAfrica, U128) {
+        let mut contract = setup();
+
+        set_context(MARKET, 0);
+        contract.on_mint_nft_callback(U128(1), "LKG0003".to_string(), None, U128(0), Ok(minted_token("3", CAROL)));
+        contract.on_transfer_token_callback_on_sale(U128(2), None, payment(BUYER, 50_000, 50_000), Ok(payout(&[(OWNER, 48_750)])));
+
+        set_context(OWNER, STORAGE);
+        contract.set_governance_rules(quorum_bps, threshold_bps, 0);
+        let proposal_id = contract.create_proposal(proposal_args("Repaint"));
+
+        (contract, proposal_id)
+    }
+
+    fn vote(contract: &mut RietsAfrica, proposal_id: U128, voter: &str, option: u32) -> u64 {
+        set_context(voter, STORAGE);
+        contract.vote(proposal_id, option)
+    }
+
+    fn finalize(contract: &mut RietsAfrica, proposal_id: U128) -> ProposalStatus {
+        set_context_at("anyone.testnet", 0, ENDS);
+        contract.finalize_proposal(proposal_id)
+    }
+
+    fn proposal_args(title: &str) -> ProposalArgs {
+        ProposalArgs {
+            property_id: U128(1),
+            title: title.to_string(),
+            description: String::new(),
+            options: vec!["Yes".to_string(), "No".to_string()],
+            starts_at: None,
+            ends_at: ENDS,
+            action: None
+        }
+    }
+
+    #[test]
+    fn proposer_pays_for_the_proposals_storage() {
+        let mut contract = setup();
+
+        set_context(OWNER, STORAGE);
+        let storage_before = env::storage_usage();
+        contract.create_proposal(proposal_args("Repaint"));
+
+        let cost = env::storage_byte_cost() * Balance::from(env::storage_usage() - storage_before);
+        assert_eq!(transfers()[OWNER], STORAGE - cost);
+    }
+
+    #[test]
+    #[should_panic(expected = "to cover storage")]
+    fn proposal_without_a_storage_deposit_is_refused() {
+        let mut contract = setup();
+
+        set_context(OWNER, 0);
+        contract.create_proposal(proposal_args("Repaint"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Title is too long")]
+    fn proposal_title_is_capped() {
+        let mut contract = setup();
+
+        set_context(OWNER, STORAGE);
+        contract.create_proposal(proposal_args(&"a".repeat(MAX_PROPOSAL_TITLE_LEN + 1)));
+    }
+
+    #[test]
+    #[should_panic(expected = "to cover storage")]
+    fn first_vote_without_a_storage_deposit_is_refused() {
+        let mut contract = setup();
+
+        set_context(OWNER, STORAGE);
+        let proposal_id = contract.create_proposal(proposal_args("Repaint"));
+
+        set_context(OWNER, 0);
+        contract.vote(proposal_id, 0);
+    }
+
+    #[test]
+    fn changing_a_vote_needs_no_further_deposit() {
+        let mut contract = setup();
+
+        set_context(OWNER, STORAGE);
+        let proposal_id = contract.create_proposal(proposal_args("Repaint"));
+        contract.vote(proposal_id, 0);
+
+        set_context(OWNER, 0);
+        assert_eq!(contract.vote(proposal_id, 1), 2);
+        assert_eq!(contract.get_proposal(proposal_id).tallies, vec![0, 2]);
+    }
+
+    #[test]
+    #[should_panic(expected = "to cover storage")]
+    fn delegation_without_a_storage_deposit_is_refused() {
+        let mut contract = setup();
+
+        set_context(OWNER, 0);
+        contract.delegate_votes(account(BUYER), None);
+    }
+
+    #[test]
+    fn proposal_passes_when_its_leading_option_clears_the_threshold() {
+        let (mut contract, proposal_id) = three_holders(5000, 6000);
+
+        vote(&mut contract, proposal_id, OWNER, 0);
+        vote(&mut contract, proposal_id, BUYER, 0);
+        vote(&mut contract, proposal_id, CAROL, 1);
+
+        assert!(finalize(&mut contract, proposal_id) == ProposalStatus::Passed);
+        assert_eq!(contract.get_proposal(proposal_id).winning_option, Some(0));
+    }
+
+    #[test]
+    fn proposal_is_rejected_when_its_leading_option_misses_the_threshold() {
+        let (mut contract, proposal_id) = three_holders(5000, 7000);
+
+        vote(&mut contract, proposal_id, OWNER, 0);
+        vote(&mut contract, proposal_id, BUYER, 0);
+        vote(&mut contract, proposal_id, CAROL, 1);
+
+        assert!(finalize(&mut contract, proposal_id) == ProposalStatus::Rejected);
+        assert_eq!(contract.get_proposal(proposal_id).winning_option, None);
+    }
+
+    #[test]
+    fn proposal_without_enough_votes_misses_its_quorum() {
+        let (mut contract, proposal_id) = three_holders(5000, 5000);
+
+        vote(&mut contract, proposal_id, OWNER, 0);
+
+        assert!(finalize(&mut contract, proposal_id) == ProposalStatus::QuorumNotMet);
+    }
+
+    #[test]
+    fn tied_proposal_is_rejected() {
+        let (mut contract, proposal_id) = three_holders(5000, 0);
+
+        vote(&mut contract, proposal_id, OWNER, 0);
+        vote(&mut contract, proposal_id, BUYER, 1);
+
+        assert!(finalize(&mut contract, proposal_id) == ProposalStatus::Rejected);
+    }
+
+    #[test]
+    fn voting_again_moves_the_vote_without_counting_it_twice() {
+        let (mut contract, proposal_id) = three_holders(5000, 5000);
+
+        vote(&mut contract, proposal_id, OWNER, 1);
+        vote(&mut contract, proposal_id, BUYER, 1);
+        vote(&mut contract, proposal_id, BUYER, 0);
+
+        let proposal = contract.get_proposal(proposal_id);
+        assert_eq!(proposal.tallies, vec![1, 1]);
+        assert_eq!(proposal.votes_cast, 2);
+    }
+
+    #[test]
+    fn direct_vote_overrides_the_delegates_vote_on_that_split() {
+        let (mut contract, proposal_id) = three_holders(5000, 5000);
+
+        set_context(CAROL, STORAGE);
+        contract.delegate_votes(account(OWNER), Some(U128(1)));
+
+        assert_eq!(vote(&mut contract, proposal_id, OWNER, 0), 2);
+        assert!(contract.get_split_vote(proposal_id, U128(3)).unwrap().delegated);
+
+        assert_eq!(vote(&mut contract, proposal_id, CAROL, 1), 1);
+        assert!(!contract.get_split_vote(proposal_id, U128(3)).unwrap().delegated);
+
+        // the delegate voting again no longer carries the split its holder voted directly
+        assert_eq!(vote(&mut contract, proposal_id, OWNER, 0), 1);
+
+        let proposal = contract.get_proposal(proposal_id);
+        assert_eq!(proposal.tallies, vec![1, 1]);
+        assert_eq!(proposal.votes_cast, 2);
+    }
+
+    #[test]
+    fn revoked_delegation_no_longer_lends_votes() {
+        let (mut contract, proposal_id) = three_holders(5000, 5000);
+
+        set_context(CAROL, STORAGE);
+        contract.delegate_votes(account(OWNER), None);
+
+        set_context(CAROL, 0);
+        contract.revoke_delegation(None);
+
+        assert_eq!(vote(&mut contract, proposal_id, OWNER, 0), 1);
+        assert_eq!(contract.get_voting_power(U128(1), account(OWNER)).delegated, 0);
+    }
+}
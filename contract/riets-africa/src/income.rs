@@ -0,0 +1,266 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::{env, log, near_bindgen, AccountId, Balance, Promise, require};
+
+use crate::{PropertySplit, RietsAfrica, RietsAfricaExt};
+
+
+#[derive(Clone, Default, BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PropertyIncome {
+    deposited: Balance,
+    // running total each split has earned since the property was created
+    per_split: Balance,
+    claimed: Balance,
+    // what did not divide evenly across the splits, carried into the next deposit
+    undistributed: Balance
+}
+
+impl PropertyIncome {
+    // adds `amount` to what each of `splits` splits has earned
+    pub fn distribute(&mut self, splits: u128, amount: Balance) {
+        let distributable = amount + self.undistributed;
+
+        self.deposited += amount;
+        self.per_split += distributable / splits;
+        self.undistributed = distributable % splits;
+    }
+
+    pub fn per_split(&self) -> Balance {
+        self.per_split
+    }
+
+    pub fn record_claim(&mut self, amount: Balance) {
+        self.claimed += amount;
+    }
+}
+
+
+#[near_bindgen]
+impl RietsAfrica {
+
+    pub fn set_property_manager(&mut self, property_id: U128, manager: Option<AccountId>) {
+        require!(env::signer_account_id() == self.owner, "Not authorised");
+
+        self.internal_set_property_manager(&property_id, manager);
+    }
+
+    pub fn get_property_manager(&self, property_id: U128) -> Option<AccountId> {
+        self.property_managers.get(&property_id)
+    }
+
+    // funds held for the property, e.g. for repairs, which only a passed proposal can release
+    #[payable]
+    pub fn deposit_reserve(&mut self, property_id: U128) {
+
+        self.require_property_manager(&property_id);
+        require!(env::attached_deposit() > 0, "Reserve deposit must be greater than zero");
+
+        let reserve = self.property_reserves.get(&property_id).unwrap_or(0);
+        self.property_reserves.insert(&property_id, &(reserve + env::attached_deposit()));
+
+        log!("Reserve of property {} topped up by {}", property_id.0, env::attached_deposit());
+    }
+
+    pub fn get_property_reserve(&self, property_id: U128) -> U128 {
+        U128::from(self.property_reserves.get(&property_id).unwrap_or(0))
+    }
+
+    // shares the attached deposit equally between the property's splits
+    #[payable]
+    pub fn deposit_income(&mut self, property_id: U128) {
+
+        self.require_property_manager(&property_id);
+
+        let property = self.properties.get((property_id.0 - 1) as u64).unwrap();
+
+        let depositor = env::signer_account_id();
+
+        require!(env::attached_deposit() > 0, "Income must be greater than zero");
+        require!(!property.split_ids.is_empty(), "Property has no splits to share income with");
+
+        self.internal_distribute_income(&property_id, property.split_ids.len() as u128, env::attached_deposit());
+
+        log!("Income of {} deposited for property {} by {}", env::attached_deposit(), property_id.0, depositor);
+    }
+
+    // claims the income built up on the caller's splits of the property, including what splits it
+    // has since sold earned while it held them
+    pub fn claim_income(&mut self, property_id: U128) -> U128 {
+
+        let account = env::signer_account_id();
+
+        let mut amount = self.settled_income.remove(&(account.clone(), property_id)).unwrap_or(0);
+
+        for property_split in self.get_income_splits(&account, &property_id) {
+            let unclaimed = self.get_split_unclaimed_income(&property_split);
+
+            if unclaimed == 0 {
+                continue;
+            }
+
+            let claimed = self.income_claimed.get(&property_split.id).unwrap_or(0);
+            self.income_claimed.insert(&property_split.id, &(claimed + unclaimed));
+
+            amount += unclaimed;
+        }
+
+        require!(amount > 0, "No income to claim");
+
+        let mut income = self.property_income.get(&property_id).unwrap();
+        income.record_claim(amount);
+        self.property_income.insert(&property_id, &income);
+
+        Promise::new(account.clone()).transfer(amount);
+
+        log!("Income of {} claimed by {}", amount, account);
+
+        U128::from(amount)
+    }
+
+    pub fn get_unclaimed_income(&self, account_id: AccountId, property_id: U128) -> U128 {
+        let settled = self.settled_income.get(&(account_id.clone(), property_id)).unwrap_or(0);
+
+        U128::from(
+            settled + self.get_income_splits(&account_id, &property_id).iter()
+                .map(|property_split| self.get_split_unclaimed_income(property_split))
+                .sum::<u128>()
+        )
+    }
+
+    pub fn get_property_income(&self, property_id: U128) -> PropertyIncome {
+        self.property_income.get(&property_id).unwrap_or_default()
+    }
+
+    pub(crate) fn internal_set_property_manager(&mut self, property_id: &U128, manager: Option<AccountId>) {
+        self.properties.get((property_id.0 - 1) as u64).unwrap_or_else(|| env::panic_str("Property id does not exist"));
+
+        match manager {
+            Some(manager) => self.property_managers.insert(property_id, &manager),
+            None => self.property_managers.remove(property_id)
+        };
+    }
+
+    pub(crate) fn internal_release_reserve(&mut self, property_id: &U128, receiver: AccountId, amount: Balance) {
+        let reserve = self.property_reserves.get(property_id).unwrap_or(0);
+
+        require!(amount <= reserve, "Amount exceeds the property reserve");
+
+        self.property_reserves.insert(property_id, &(reserve - amount));
+
+        Promise::new(receiver.clone()).transfer(amount);
+
+        log!("Reserve of {} released from property {} to {}", amount, property_id.0, receiver);
+    }
+
+    // the contract owner can act for any property, its manager only for that property
+    pub(crate) fn require_property_manager(&self, property_id: &U128) {
+        self.properties.get((property_id.0 - 1) as u64).unwrap_or_else(|| env::panic_str("Property id does not exist"));
+
+        let signer = env::signer_account_id();
+
+        require!(signer == self.owner || Some(signer) == self.property_managers.get(property_id), "Not authorised");
+    }
+
+    pub(crate) fn internal_distribute_income(&mut self, property_id: &U128, splits: u128, amount: Balance) {
+        let mut income = self.property_income.get(property_id).unwrap_or_default();
+
+        income.distribute(splits, amount);
+
+        self.property_income.insert(property_id, &income);
+    }
+
+    // a split minted after income was deposited only earns from later deposits
+    pub(crate) fn start_split_income(&mut self, property_id: &U128, property_split_id: &U128) {
+        if let Some(income) = self.property_income.get(property_id) {
+            self.income_claimed.insert(property_split_id, &income.per_split);
+        }
+    }
+
+    // income a split earned under its current owner stays theirs when it changes hands
+    pub(crate) fn settle_split_income(&mut self, property_split: &PropertySplit) {
+        let unclaimed = self.get_split_unclaimed_income(property_split);
+
+        if unclaimed == 0 {
+            return;
+        }
+
+        let key = (property_split.owner.clone(), property_split.property_id);
+        let settled = self.settled_income.get(&key).unwrap_or(0);
+        self.settled_income.insert(&key, &(settled + unclaimed));
+
+        let claimed = self.income_claimed.get(&property_split.id).unwrap_or(0);
+        self.income_claimed.insert(&property_split.id, &(claimed + unclaimed));
+    }
+
+    fn get_split_unclaimed_income(&self, property_split: &PropertySplit) -> Balance {
+        let per_split = self.property_income.get(&property_split.property_id).map(|income| income.per_split).unwrap_or(0);
+
+        per_split - self.income_claimed.get(&property_split.id).unwrap_or(0)
+    }
+
+    fn get_income_splits(&self, account: &AccountId, property_id: &U128) -> Vec<PropertySplit> {
+        let property = self.properties.get((property_id.0 - 1) as u64).unwrap_or_else(|| env::panic_str("Property id does not exist"));
+
+        property.split_ids.iter()
+            .map(|split_id| self.property_splits.get((split_id.0 - 1) as u64).unwrap())
+            .filter(|property_split| property_split.owner == *account)
+            .collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{account, payment, payout, set_context, setup, transfers, BUYER, MARKET, OWNER};
+
+    #[test]
+    fn income_that_does_not_divide_evenly_is_carried_into_the_next_deposit() {
+        let mut contract = setup();
+
+        set_context(OWNER, 101);
+        contract.deposit_income(U128(1));
+
+        let income = contract.get_property_income(U128(1));
+        assert_eq!(income.per_split, 50);
+        assert_eq!(income.undistributed, 1);
+
+        set_context(OWNER, 101);
+        contract.deposit_income(U128(1));
+
+        let income = contract.get_property_income(U128(1));
+        assert_eq!(income.deposited, 202);
+        assert_eq!(income.per_split, 101);
+        assert_eq!(income.undistributed, 0);
+    }
+
+    #[test]
+    fn seller_keeps_the_income_earned_before_a_sale_and_the_buyer_earns_after_it() {
+        let mut contract = setup();
+
+        set_context(OWNER, 100_000);
+        contract.deposit_income(U128(1));
+
+        set_context(MARKET, 0);
+        contract.on_transfer_token_callback_on_sale(U128(1), None, payment(BUYER, 50_000, 50_000), Ok(payout(&[(OWNER, 48_750)])));
+
+        set_context(OWNER, 100_000);
+        contract.deposit_income(U128(1));
+
+        assert_eq!(contract.get_unclaimed_income(account(OWNER), U128(1)).0, 150_000);
+        assert_eq!(contract.get_unclaimed_income(account(BUYER), U128(1)).0, 50_000);
+
+        set_context(OWNER, 0);
+        assert_eq!(contract.claim_income(U128(1)).0, 150_000);
+        assert_eq!(transfers()[OWNER], 150_000);
+
+        set_context(BUYER, 0);
+        assert_eq!(contract.claim_income(U128(1)).0, 50_000);
+        assert_eq!(transfers()[BUYER], 50_000);
+
+        assert_eq!(contract.get_property_income(U128(1)).claimed, 200_000);
+        assert_eq!(contract.get_unclaimed_income(account(OWNER), U128(1)).0, 0);
+    }
+}
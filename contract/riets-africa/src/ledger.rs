@@ -0,0 +1,223 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::Vector;
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, log, near_bindgen, AccountId, Balance, require};
+
+use crate::{RietsAfrica, RietsAfricaExt};
+
+pub const MAX_LEDGER_PAGE: u64 = 50;
+
+
+#[derive(Clone, PartialEq, BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub enum EntryKind {
+    Income,
+    Expense
+}
+
+#[derive(Clone, PartialEq, BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub enum EntryCategory {
+    Rent,
+    OtherIncome,
+    Maintenance,
+    Tax,
+    ManagementFee,
+    Insurance,
+    Utilities,
+    Other
+}
+
+#[derive(Clone, BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LedgerEntry {
+    id: U128,
+    kind: EntryKind,
+    category: EntryCategory,
+    // in yoctoNEAR, so the net income of a period is what gets distributed
+    amount: Balance,
+    // accounting period label chosen by the manager, e.g. 2024-06
+    period: String,
+    // reference to the invoice, receipt or statement behind the entry
+    memo: Option<String>,
+    recorded_by: AccountId,
+    recorded_at: u64
+}
+
+#[derive(Clone, BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CategoryTotal {
+    category: EntryCategory,
+    amount: Balance
+}
+
+// profit and loss of one property over one period
+#[derive(Clone, BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PeriodSummary {
+    period: String,
+    income: Balance,
+    expenses: Balance,
+    net_income: Balance,
+    loss: Balance,
+    distributed: Balance,
+    by_category: Vec<CategoryTotal>
+}
+
+impl PeriodSummary {
+    pub fn new(period: String) -> Self {
+        Self {
+            period,
+            income: 0,
+            expenses: 0,
+            net_income: 0,
+            loss: 0,
+            distributed: 0,
+            by_category: Vec::new()
+        }
+    }
+
+    pub fn add_entry(&mut self, entry: &LedgerEntry) {
+        match entry.kind {
+            EntryKind::Income => self.income += entry.amount,
+            EntryKind::Expense => self.expenses += entry.amount
+        }
+
+        match self.by_category.iter_mut().find(|total| total.category == entry.category) {
+            Some(total) => total.amount += entry.amount,
+            None => self.by_category.push(CategoryTotal { category: entry.category.clone(), amount: entry.amount })
+        }
+
+        self.net_income = self.income.saturating_sub(self.expenses);
+        self.loss = self.expenses.saturating_sub(self.income);
+    }
+
+    pub fn distributable(&self) -> Balance {
+        self.net_income.saturating_sub(self.distributed)
+    }
+}
+
+// every property keeps its entries under its own storage prefix
+fn ledger_prefix(property_id: &U128) -> Vec<u8> {
+    let mut prefix = b"E".to_vec();
+    prefix.extend(property_id.0.to_le_bytes());
+    prefix
+}
+
+
+#[near_bindgen]
+impl RietsAfrica {
+
+    pub fn record_ledger_entry(
+        &mut self,
+        property_id: U128,
+        kind: EntryKind,
+        category: EntryCategory,
+        amount: U128,
+        period: String,
+        memo: Option<String>
+    ) -> U128 {
+
+        self.require_property_manager(&property_id);
+        require!(amount.0 > 0, "Amount must be greater than zero");
+        require!(!period.is_empty(), "Period cannot be empty");
+
+        match kind {
+            EntryKind::Income => require!(matches!(category, EntryCategory::Rent | EntryCategory::OtherIncome), "Category is not an income category"),
+            EntryKind::Expense => require!(!matches!(category, EntryCategory::Rent | EntryCategory::OtherIncome), "Category is not an expense category")
+        }
+
+        let mut entries = self.ledger_entries.get(&property_id).unwrap_or(Vector::new(ledger_prefix(&property_id)));
+
+        let entry_id = U128::from(u128::from(entries.len()) + 1);
+
+        let entry = LedgerEntry {
+            id: entry_id,
+            kind,
+            category,
+            amount: amount.0,
+            period: period.clone(),
+            memo,
+            recorded_by: env::signer_account_id(),
+            recorded_at: env::block_timestamp_ms()
+        };
+
+        entries.push(&entry);
+        self.ledger_entries.insert(&property_id, &entries);
+
+        let mut summary = self.get_period_summary(property_id, period.clone());
+        summary.add_entry(&entry);
+        self.save_period_summary(&property_id, &summary);
+
+        entry_id
+    }
+
+    // distributes what the books say is left of a period's net income; the deposit must match it.
+    // Tied to a snapshot, the income goes to the holders at its record date instead of the current owners
+    #[payable]
+    pub fn distribute_net_income(&mut self, property_id: U128, period: String, snapshot_id: Option<U128>) {
+
+        self.require_property_manager(&property_id);
+
+        let property = self.properties.get((property_id.0 - 1) as u64).unwrap();
+
+        let mut summary = self.ledger_periods.get(&(property_id, period.clone())).unwrap_or_else(|| env::panic_str("Period has no entries"));
+
+        let distributable = summary.distributable();
+
+        require!(distributable > 0, "Period has no income left to distribute");
+        require!(env::attached_deposit() == distributable, "Deposit must equal the period's distributable income");
+
+        match &snapshot_id {
+            Some(snapshot_id) => {
+                require!(self.get_snapshot(*snapshot_id).property_id == property_id, "Snapshot is of another property");
+
+                self.internal_distribute_snapshot_income(snapshot_id, distributable);
+            }
+            None => {
+                require!(!property.split_ids.is_empty(), "Property has no splits to share income with");
+
+                self.internal_distribute_income(&property_id, property.split_ids.len() as u128, distributable);
+            }
+        }
+
+        summary.distributed += distributable;
+        self.save_period_summary(&property_id, &summary);
+
+        log!("Net income of {} for period {} distributed on property {}", distributable, period, property_id.0);
+    }
+
+    pub fn get_ledger_entries(&self, property_id: U128, from_index: Option<u64>, limit: Option<u64>) -> Vec<LedgerEntry> {
+        let entries = match self.ledger_entries.get(&property_id) {
+            Some(entries) => entries,
+            None => return Vec::new()
+        };
+
+        entries.iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(MAX_LEDGER_PAGE).min(MAX_LEDGER_PAGE) as usize)
+            .collect()
+    }
+
+    pub fn get_period_summary(&self, property_id: U128, period: String) -> PeriodSummary {
+        self.ledger_periods.get(&(property_id, period.clone())).unwrap_or_else(|| PeriodSummary::new(period))
+    }
+
+    // profit and loss for every period of the property, in the order the periods were first recorded
+    pub fn get_profit_and_loss(&self, property_id: U128) -> Vec<PeriodSummary> {
+        self.property_periods.get(&property_id).unwrap_or_default().into_iter()
+            .map(|period| self.get_period_summary(property_id, period))
+            .collect()
+    }
+
+    fn save_period_summary(&mut self, property_id: &U128, summary: &PeriodSummary) {
+        let key = (*property_id, summary.period.clone());
+
+        if self.ledger_periods.insert(&key, summary).is_none() {
+            let mut periods = self.property_periods.get(property_id).unwrap_or_default();
+            periods.push(summary.period.clone());
+            self.property_periods.insert(property_id, &periods);
+        }
+    }
+}
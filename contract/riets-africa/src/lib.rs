@@ -1,14 +1,54 @@
 
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LookupMap, Vector};
+use near_sdk::collections::{LookupMap, LookupSet, Vector};
 use near_sdk::json_types::U128;
-use near_sdk::serde::Serialize;
-use near_sdk::{env, log, near_bindgen, AccountId, Balance, Gas, Promise, ext_contract, require};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, log, near_bindgen, AccountId, Balance, Gas, Promise, PromiseError, ext_contract, require};
+
+use payment::{send_payment, send_treasury_payment};
+use governance::{DEFAULT_QUORUM_BPS, DEFAULT_THRESHOLD_BPS, DEFAULT_TIMELOCK_MS};
+use valuation::{require_currency_code, DEFAULT_MAX_RATE_AGE_MS};
 use near_contract_standards::non_fungible_token::{Token, TokenId, metadata::TokenMetadata};
+use std::collections::HashMap;
 use std::convert::From;
+use std::ops::Range;
+
+mod auction;
+mod bundle;
+mod dutch_auction;
+mod governance;
+mod income;
+mod ledger;
+mod offering;
+mod payment;
+mod property_sale;
+mod raise;
+mod snapshot;
+mod valuation;
+
+pub use auction::{Auction, AuctionKind, AuctionStatus, SealedBid};
+pub use bundle::{Bundle, BundleStatus};
+pub use dutch_auction::DutchAuction;
+pub use governance::{Delegation, Proposal, ProposalAction, ProposalArgs, ProposalStatus, SplitVote, VotingPower};
+pub use income::PropertyIncome;
+pub use ledger::{CategoryTotal, EntryCategory, EntryKind, LedgerEntry, PeriodSummary};
+pub use offering::PrimaryOffering;
+pub use payment::FtPaymentMessage;
+pub use property_sale::PropertySale;
+pub use raise::{Pledge, Raise, RaiseArgs, RaiseStatus};
+pub use snapshot::{Snapshot, SnapshotHolding};
+pub use valuation::ExchangeRate;
 
 pub const NFT_CONTRACT: &str = "token.reit-africa.testnet";
 pub const XCC_GAS: Gas = Gas(20000000000000);
+pub const MAX_FEE_BPS: u16 = 10000;
+pub const MAX_LEN_PAYOUT: u32 = 10;
+pub const MAX_BATCH_PURCHASE: usize = 10;
+pub const MAX_OPEN_OFFERS: usize = 20;
+pub const FT_TRANSFER_GAS: Gas = Gas(5000000000000);
+pub const FT_RESOLVE_GAS: Gas = Gas(5000000000000);
+// recording one split changing hands, refunding its open offers included
+pub const SPLIT_SETTLE_GAS: Gas = Gas(10000000000000);
 
 
 #[derive(Clone, BorshDeserialize, BorshSerialize, Serialize)]
@@ -18,25 +58,29 @@ pub struct Property {
     name: String,
     image: String,
     property_identifier: String,
+    // in minor units of `valuation_currency`, or yoctoNEAR when no currency is set
     valuation: Balance,
+    valuation_currency: Option<String>,
     split_ids: Vec<U128>
 }
 
 
 impl Property {
-    pub fn new(id_: U128, name_: String, property_identifier_: String, valuation_: Balance, image_: String) -> Self {
+    pub fn new(id_: U128, name_: String, property_identifier_: String, valuation_: Balance, valuation_currency_: Option<String>, image_: String) -> Self {
         Self {
             id: id_.clone(),
             name: name_,
             image: image_,
             property_identifier: property_identifier_,
             valuation: valuation_,
+            valuation_currency: valuation_currency_,
             split_ids: Vec::new()
         }
     }
 
-    pub fn set_valuation(&mut self, value: u128) -> bool {
+    pub fn set_valuation(&mut self, value: u128, currency: Option<String>) -> bool {
         self.valuation = value;
+        self.valuation_currency = currency;
         true
     }
 }
@@ -49,6 +93,7 @@ pub struct PropertyWithSplits {
     image: String,
     property_identifier: String,
     valuation: Balance,
+    valuation_currency: Option<String>,
     property_splits: Vec<PropertySplit>,
 }
 
@@ -60,6 +105,7 @@ pub struct PropertyWithSplit {
     image: String,
     property_identifier: String,
     valuation: Balance,
+    valuation_currency: Option<String>,
     property_splits: Vec<PropertySplit>,
 }
 
@@ -71,6 +117,7 @@ impl From<Property> for PropertyWithSplit {
             image: property.image,
             property_identifier: property.property_identifier,
             valuation: property.valuation,
+            valuation_currency: property.valuation_currency,
             property_splits: Vec::new()
         }
     }
@@ -86,7 +133,83 @@ pub struct PropertySplit {
     token_metadata: TokenMetadata,
     owner: AccountId,
     last_sale_date: u64,
-    on_sale: bool
+    on_sale: bool,
+    sale_price: Balance,
+    // listing window as block timestamps in milliseconds
+    sale_starts_at: u64,
+    sale_ends_at: Option<u64>,
+    // NEP-141 token the listing is priced in, NEAR when not set
+    sale_currency: Option<AccountId>,
+    // NEP-178 approval the listing was created from, used when the split is transferred
+    sale_approval_id: Option<u64>
+}
+
+impl PropertySplit {
+    pub fn is_sale_expired(&self) -> bool {
+        match self.sale_ends_at {
+            Some(ends_at) => env::block_timestamp_ms() >= ends_at,
+            None => false
+        }
+    }
+
+    pub fn is_sale_open(&self) -> bool {
+        self.on_sale && env::block_timestamp_ms() >= self.sale_starts_at && !self.is_sale_expired()
+    }
+
+    pub fn clear_listing(&mut self) {
+        self.on_sale = false;
+        self.sale_price = 0;
+        self.sale_starts_at = 0;
+        self.sale_ends_at = None;
+        self.sale_currency = None;
+        self.sale_approval_id = None;
+    }
+}
+
+// the `msg` a seller passes to `nft_approve` on the token contract to list a split
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SaleArgs {
+    price: U128,
+    starts_at: Option<u64>,
+    ends_at: Option<u64>,
+    currency: Option<AccountId>
+}
+
+// what `create_property` needs to register a property and mint a split per document
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PropertyArgs {
+    pub name: String,
+    pub image_url: String,
+    pub identifier: String,
+    pub valuation: U128,
+    pub valuation_currency: Option<String>,
+    pub doc_urls: Vec<String>,
+    pub royalty: Option<HashMap<AccountId, u32>>
+}
+
+#[derive(Clone, PartialEq, BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub enum OfferStatus {
+    Pending,
+    Accepted,
+    Cancelled,
+    Expired,
+    Refunded,
+    Countered,
+    Rejected,
+    Declined
+}
+
+#[derive(Clone, BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OfferEvent {
+    status: OfferStatus,
+    // offer value or counter price at the time of the event
+    price: Balance,
+    actor: AccountId,
+    timestamp: u64
 }
 
 #[derive(Clone, BorshDeserialize, BorshSerialize, Serialize)]
@@ -94,10 +217,109 @@ pub struct PropertySplit {
 pub struct PurchaseOffer {
     id: U128,
     value: Balance,
+    // NEP-141 token the deposit was made in, NEAR when not set
+    currency: Option<AccountId>,
     buyer: AccountId,
-    token_id: TokenId
+    token_id: TokenId,
+    // block timestamp in milliseconds after which the offer can no longer be accepted
+    expires_at: Option<u64>,
+    status: OfferStatus,
+    counter_price: Option<Balance>,
+    history: Vec<OfferEvent>
 }
 
+impl PurchaseOffer {
+    // offers waiting on either side of the negotiation still hold the buyer's deposit
+    pub fn is_open(&self) -> bool {
+        self.status == OfferStatus::Pending || self.status == OfferStatus::Countered
+    }
+
+    pub fn set_status(&mut self, status: OfferStatus, actor: AccountId) {
+        let price = match status {
+            OfferStatus::Countered => self.counter_price.unwrap_or(self.value),
+            _ => self.value
+        };
+
+        self.history.push(OfferEvent {
+            status: status.clone(),
+            price,
+            actor,
+            timestamp: env::block_timestamp_ms()
+        });
+        self.status = status;
+    }
+
+    pub fn is_expired(&self) -> bool {
+        match self.expires_at {
+            Some(expires_at) => env::block_timestamp_ms() >= expires_at,
+            None => false
+        }
+    }
+}
+
+#[derive(Clone, BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SaleRecord {
+    property_split_id: U128,
+    seller: AccountId,
+    buyer: AccountId,
+    currency: Option<AccountId>,
+    price: Balance,
+    fee: Balance,
+    royalties: Balance,
+    refund: Balance,
+    bundle_id: Option<U128>,
+    sale_date: u64
+}
+
+#[derive(Clone, BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TreasuryInfo {
+    platform_fee_bps: u16,
+    balance: Balance,
+    fees_collected: Balance
+}
+
+
+// every split keeps its offers under its own storage prefix
+fn offers_prefix(property_split_id: &U128) -> Vec<u8> {
+    let mut prefix = b"l".to_vec();
+    prefix.extend(property_split_id.0.to_le_bytes());
+    prefix
+}
+
+// and its sales under another
+fn sales_prefix(property_split_id: &U128) -> Vec<u8> {
+    let mut prefix = b"S".to_vec();
+    prefix.extend(property_split_id.0.to_le_bytes());
+    prefix
+}
+
+// a Dutch auction or primary offering counts as running from when it is started, even if scheduled for
+// later, until it has ended and every sale it made has resolved
+fn is_issuer_sale_running(ends_at: u64, pending_split_ids: &[U128]) -> bool {
+    env::block_timestamp_ms() < ends_at || !pending_split_ids.is_empty()
+}
+
+
+// the money side of a sale, carried to the callback that settles it once the token has moved
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SalePayment {
+    seller: AccountId,
+    buyer: AccountId,
+    // NEP-141 token the sale is paid in, NEAR when not set
+    currency: Option<AccountId>,
+    price: U128,
+    fee: U128,
+    deposit: U128
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Payout {
+    payout: HashMap<AccountId, U128>
+}
 
 
 #[ext_contract(ext_nft_contract)]
@@ -108,7 +330,8 @@ trait RietsToken {
         property_identifier: String,
         split_identifier: String,
         doc_url: String,
-        image_url: String
+        image_url: String,
+        royalty: Option<HashMap<AccountId, u32>>
     ) -> Token;
 
     fn get_user_properties(
@@ -121,6 +344,25 @@ trait RietsToken {
         token_id: TokenId, 
         receiver: AccountId
     );
+
+    fn nft_transfer_payout(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: TokenId,
+        approval_id: Option<u64>,
+        memo: Option<String>,
+        balance: U128,
+        max_len_payout: Option<u32>
+    ) -> Payout;
+
+    fn nft_batch_transfer_payout(
+        &mut self,
+        receiver_id: AccountId,
+        token_ids: Vec<TokenId>,
+        memo: Option<String>,
+        balance: U128,
+        max_len_payout: Option<u32>
+    ) -> Payout;
 }
 
 
@@ -131,7 +373,55 @@ pub struct RietsAfrica {
     property_splits: Vector<PropertySplit>,
     owner: AccountId,
     property_split_by_token_id: LookupMap<TokenId, PropertySplit>,
-    offers: LookupMap<U128, Vector<PurchaseOffer>>
+    offers: LookupMap<U128, Vector<PurchaseOffer>>,
+    // ids of the offers on each split still holding a deposit, so a sale only has to visit those
+    open_offers: LookupMap<U128, Vec<U128>>,
+    sales: LookupMap<U128, Vector<SaleRecord>>,
+    // NEP-178 approval each split's current holder has given the marketplace on its token
+    split_approvals: LookupMap<U128, u64>,
+    refunds: LookupMap<(AccountId, Option<AccountId>), Balance>,
+    failed_mints: LookupMap<U128, Vec<String>>,
+    platform_fee_bps: u16,
+    property_fee_bps: LookupMap<U128, u16>,
+    treasury: Balance,
+    fees_collected: Balance,
+    token_treasury: LookupMap<AccountId, Balance>,
+    token_fees_collected: LookupMap<AccountId, Balance>,
+    accepted_tokens: LookupSet<AccountId>,
+    auctions: Vector<Auction>,
+    active_auctions: LookupMap<U128, U128>,
+    bundles: Vector<Bundle>,
+    bundled_splits: LookupMap<U128, U128>,
+    sealed_bids: LookupMap<U128, Vec<SealedBid>>,
+    dutch_auctions: LookupMap<U128, DutchAuction>,
+    primary_offerings: LookupMap<U128, PrimaryOffering>,
+    offering_purchases: LookupMap<(U128, AccountId), u64>,
+    reserved_splits: LookupSet<U128>,
+    raises: Vector<Raise>,
+    rate_feeder: Option<AccountId>,
+    exchange_rates: LookupMap<String, ExchangeRate>,
+    max_rate_age_ms: u64,
+    property_managers: LookupMap<U128, AccountId>,
+    property_income: LookupMap<U128, PropertyIncome>,
+    income_claimed: LookupMap<U128, Balance>,
+    // income earned on splits the account has since sold, by account and property
+    settled_income: LookupMap<(AccountId, U128), Balance>,
+    ledger_entries: LookupMap<U128, Vector<LedgerEntry>>,
+    ledger_periods: LookupMap<(U128, String), PeriodSummary>,
+    property_periods: LookupMap<U128, Vec<String>>,
+    snapshots: Vector<Snapshot>,
+    property_snapshots: LookupMap<U128, Vec<U128>>,
+    snapshot_income_claimed: LookupMap<(U128, U128), Balance>,
+    proposals: Vector<Proposal>,
+    property_proposals: LookupMap<U128, Vec<U128>>,
+    split_votes: LookupMap<(U128, U128), SplitVote>,
+    governance_quorum_bps: u16,
+    governance_threshold_bps: u16,
+    governance_timelock_ms: u64,
+    delegations: LookupMap<(AccountId, Option<U128>), AccountId>,
+    delegators: LookupMap<AccountId, Vec<Delegation>>,
+    property_reserves: LookupMap<U128, Balance>,
+    property_sales: LookupMap<U128, PropertySale>
 }
 
 impl Default for RietsAfrica {
@@ -141,7 +431,52 @@ impl Default for RietsAfrica {
             property_splits: Vector::new(b"q"),
             owner: env::signer_account_id(),
             property_split_by_token_id: LookupMap::new(b"p"),
-            offers: LookupMap::new(b"o")
+            offers: LookupMap::new(b"o"),
+            open_offers: LookupMap::new(b"B"),
+            sales: LookupMap::new(b"s"),
+            split_approvals: LookupMap::new(b"A"),
+            refunds: LookupMap::new(b"r"),
+            failed_mints: LookupMap::new(b"f"),
+            platform_fee_bps: 0,
+            property_fee_bps: LookupMap::new(b"b"),
+            treasury: 0,
+            fees_collected: 0,
+            token_treasury: LookupMap::new(b"t"),
+            token_fees_collected: LookupMap::new(b"x"),
+            accepted_tokens: LookupSet::new(b"y"),
+            auctions: Vector::new(b"a"),
+            active_auctions: LookupMap::new(b"c"),
+            bundles: Vector::new(b"g"),
+            bundled_splits: LookupMap::new(b"h"),
+            sealed_bids: LookupMap::new(b"e"),
+            dutch_auctions: LookupMap::new(b"d"),
+            primary_offerings: LookupMap::new(b"m"),
+            offering_purchases: LookupMap::new(b"u"),
+            reserved_splits: LookupSet::new(b"i"),
+            raises: Vector::new(b"w"),
+            rate_feeder: None,
+            exchange_rates: LookupMap::new(b"n"),
+            max_rate_age_ms: DEFAULT_MAX_RATE_AGE_MS,
+            property_managers: LookupMap::new(b"v"),
+            property_income: LookupMap::new(b"j"),
+            income_claimed: LookupMap::new(b"z"),
+            settled_income: LookupMap::new(b"I"),
+            ledger_entries: LookupMap::new(b"L"),
+            ledger_periods: LookupMap::new(b"P"),
+            property_periods: LookupMap::new(b"Q"),
+            snapshots: Vector::new(b"N"),
+            property_snapshots: LookupMap::new(b"R"),
+            snapshot_income_claimed: LookupMap::new(b"O"),
+            proposals: Vector::new(b"G"),
+            property_proposals: LookupMap::new(b"H"),
+            split_votes: LookupMap::new(b"V"),
+            governance_quorum_bps: DEFAULT_QUORUM_BPS,
+            governance_threshold_bps: DEFAULT_THRESHOLD_BPS,
+            governance_timelock_ms: DEFAULT_TIMELOCK_MS,
+            delegations: LookupMap::new(b"D"),
+            delegators: LookupMap::new(b"F"),
+            property_reserves: LookupMap::new(b"X"),
+            property_sales: LookupMap::new(b"W")
         }
     }
 }
@@ -150,54 +485,86 @@ impl Default for RietsAfrica {
 #[near_bindgen]
 impl RietsAfrica {
 
-    pub fn create_property(&mut self, name: String, image_url: String, identifier: String, valuation: U128, doc_urls: Vec<String>) {
-      
+    // a valuation with a currency is held in that currency's minor units and priced in NEAR through the rate feed
+    pub fn create_property(&mut self, property: PropertyArgs) {
+
+        if let Some(currency) = &property.valuation_currency {
+            require_currency_code(currency);
+        }
+
+        let property_id = self.internal_create_property(&property);
+
+        self.mint_property_splits(property_id, &property, 0..property.doc_urls.len(), &[], 0);
+    }
+
+    // records the property; its splits are minted separately through `mint_property_splits`
+    fn internal_create_property(&mut self, args: &PropertyArgs) -> U128 {
 
         let new_property_id = U128::from(u128::from(self.properties.len()) + 1);
 
         let property = Property::new(
             new_property_id.clone(), 
-            name, 
-            identifier.clone(), 
-            valuation.0, 
-            image_url.clone()
+            args.name.clone(), 
+            args.identifier.clone(), 
+            args.valuation.0, 
+            args.valuation_currency.clone(),
+            args.image_url.clone()
         );
         self.properties.push(&property);
 
-        let mut split_id = 1;
+        new_property_id
+    }
+
+    // mints a split for each document in `splits`; the first splits go to `purchasers`, who have
+    // already paid `price` each, and the rest to the contract owner
+    fn mint_property_splits(&mut self, property_id: U128, args: &PropertyArgs, splits: Range<usize>, purchasers: &[AccountId], price: Balance) {
+
+        let PropertyArgs { image_url, identifier, doc_urls, royalty, .. } = args;
 
-        for  doc in doc_urls {
+        for index in splits {
 
+            let split_id = index + 1;
             let id_length = &split_id.to_string().chars().count();
             let property_identifier = identifier.clone();
             let zero_spacing = "0".repeat(4 - id_length);
 
             let split_identifier = format!("{}{}{}", property_identifier.to_string(), zero_spacing, &split_id.to_string());
 
-            split_id += 1;
+            let purchaser = purchasers.get(index).cloned();
+            let token_owner = purchaser.clone().unwrap_or(self.owner.clone());
 
             ext_nft_contract::ext(AccountId::new_unchecked(NFT_CONTRACT.to_string()))
             .nft_mint(
-                self.owner.clone(),
+                token_owner,
                 identifier.clone(),
                 split_identifier.clone(),
-                doc,
-                image_url.clone())
+                doc_urls[index].clone(),
+                image_url.clone(),
+                royalty.clone())
             .then(
                 Self::ext(env::current_account_id())
                     .with_static_gas(XCC_GAS)
-                    .on_mint_nft_callback(new_property_id.clone(), split_identifier) 
+                    .on_mint_nft_callback(property_id, split_identifier, purchaser, U128::from(price))
             );
 
             
         }
     }
 
-    pub fn set_property_valuation(&mut self, property_id: U128, new_valuation: U128) {
+    pub fn set_property_valuation(&mut self, property_id: U128, new_valuation: U128, currency: Option<String>) {
         require!(env::signer_account_id() == self.owner, "Not authorised");
+
+        self.internal_set_property_valuation(&property_id, new_valuation.0, currency);
+    }
+
+    // shared by the owner call and passed governance proposals
+    fn internal_set_property_valuation(&mut self, property_id: &U128, new_valuation: Balance, currency: Option<String>) {
+        if let Some(code) = &currency {
+            require_currency_code(code);
+        }
         let id = property_id.clone();
         let mut prop = self.properties.get((id.0 - 1) as u64).unwrap_or_else(|| env::panic_str("Property id does not exist"));
-        prop.set_valuation(new_valuation.0);
+        prop.set_valuation(new_valuation, currency);
 
         self.properties.replace((id.0 - 1) as u64, &prop);
 
@@ -205,32 +572,104 @@ impl RietsAfrica {
         // self.properties[u128::from(&property_id)] = property;
     }
 
+    pub fn set_platform_fee(&mut self, fee_bps: u16) {
+        require!(env::signer_account_id() == self.owner, "Not authorised");
+        require!(fee_bps <= MAX_FEE_BPS, "Fee cannot exceed 10000 basis points");
+
+        self.platform_fee_bps = fee_bps;
+    }
+
+    // overrides the platform fee for one property; passing no fee falls back to the platform fee
+    pub fn set_property_fee(&mut self, property_id: U128, fee_bps: Option<u16>) {
+        require!(env::signer_account_id() == self.owner, "Not authorised");
+        self.properties.get((property_id.0 - 1) as u64).unwrap_or_else(|| env::panic_str("Property id does not exist"));
+
+        match fee_bps {
+            Some(fee) => {
+                require!(fee <= MAX_FEE_BPS, "Fee cannot exceed 10000 basis points");
+                self.property_fee_bps.insert(&property_id, &fee);
+            }
+            None => {
+                self.property_fee_bps.remove(&property_id);
+            }
+        }
+    }
+
+    pub fn withdraw_treasury(&mut self, receiver: AccountId, amount: U128, currency: Option<AccountId>) {
+        require!(env::signer_account_id() == self.owner, "Not authorised");
+
+        let balance = match &currency {
+            Some(token) => self.token_treasury.get(token).unwrap_or(0),
+            None => self.treasury
+        };
+
+        require!(amount.0 > 0 && amount.0 <= balance, "Amount exceeds treasury balance");
+
+        match &currency {
+            Some(token) => {
+                self.token_treasury.insert(token, &(balance - amount.0));
+            }
+            None => self.treasury -= amount.0
+        }
+
+        send_treasury_payment(&currency, receiver.clone(), amount.0);
+
+        log!("Withdrew {} from treasury to {}", amount.0, receiver);
+    }
+
     #[payable]
-    pub fn make_property_offer(&mut self, property_split_id: U128) {
-        
+    pub fn make_property_offer(&mut self, property_split_id: U128, expires_at: Option<u64>) {
+        self.internal_make_offer(property_split_id, env::signer_account_id(), env::attached_deposit(), None, expires_at);
+    }
+
+    // a NEAR offer must be at least the split's value. The value is priced in NEAR, so a token offer is
+    // held to the listing's price instead when the split is listed in that token
+    fn internal_make_offer(&mut self, property_split_id: U128, buyer: AccountId, value: Balance, currency: Option<AccountId>, expires_at: Option<u64>) {
+
         let choice_split = self.property_splits.get((property_split_id.clone().0 -1) as u64).unwrap_or_else(|| env::panic_str("Split id does not exist"));
 
-        let buyer = env::signer_account_id();
+        let minimum = match &currency {
+            None => self.get_split_value(&property_split_id),
+            Some(_) if choice_split.sale_currency == currency => choice_split.sale_price,
+            Some(_) => 0
+        };
+
+        require!(value > 0 && value >= minimum, "Not sufficient deposit to make offer");
 
         require!(buyer != self.owner && buyer != choice_split.owner.clone(), "Not authorized");
 
-        require!(env::attached_deposit() >= self.get_split_value(&property_split_id), "Not sufficient deposit to make offer");
+        if let Some(expiry) = expires_at {
+            require!(expiry > env::block_timestamp_ms(), "Offer expiry must be in the future");
+        }
+
+        let mut open_offers = self.open_offers.get(&property_split_id).unwrap_or_default();
 
-        let mut previous_offers_on_split = self.offers.get(&property_split_id).unwrap_or(Vector::new(b"l"));
+        require!(open_offers.len() < MAX_OPEN_OFFERS, "Too many open offers on this split");
+
+        let mut previous_offers_on_split = self.offers.get(&property_split_id).unwrap_or(Vector::new(offers_prefix(&property_split_id)));
 
         let offer_id = U128::from(u128::from((previous_offers_on_split.len()) + 1));
 
-        let offer = PurchaseOffer {
+        let mut offer = PurchaseOffer {
             id: offer_id,
-            value: env::attached_deposit(),
-            buyer: env::signer_account_id(),
-            token_id: choice_split.token_id.clone()
+            value,
+            currency,
+            buyer: buyer.clone(),
+            token_id: choice_split.token_id.clone(),
+            expires_at,
+            status: OfferStatus::Pending,
+            counter_price: None,
+            history: Vec::new()
         };
+        offer.set_status(OfferStatus::Pending, buyer);
 
         previous_offers_on_split.push(&offer);
 
         self.offers.insert(&property_split_id, &previous_offers_on_split);
 
+        open_offers.push(offer_id);
+        self.open_offers.insert(&property_split_id, &open_offers);
+
     }
 
 
@@ -244,75 +683,281 @@ impl RietsAfrica {
             env::signer_account_id() == property_split.owner
         }, "Not authorised to sell this property");
 
-        let offers_on_split = self.offers.get(&property_split_id).unwrap_or_else(|| env::panic_str("No offer on this property"));
+        let offer = self.get_offer(&property_split_id, &offer_id);
 
-        let offer = offers_on_split.get((offer_id.clone().0 as u64) - 1).unwrap_or_else(|| env::panic_str("Offer id does not exist"));
+        require!(offer.is_open(), "Offer is no longer open");
+        require!(!offer.is_expired(), "Offer has expired");
 
-        ext_nft_contract::ext(AccountId::new_unchecked(NFT_CONTRACT.to_string()))
-            .transfer_token(
-                offer.token_id.clone(),
-                offer.buyer.clone())
-            .then(
-                Self::ext(env::current_account_id())
-                    .with_static_gas(XCC_GAS)
-                    .on_transfer_token_callback_on_sale(property_split_id, &property_split.token_id, offer.buyer.clone()) 
-            );
+        let price = offer.value;
 
+        self.settle_offer(property_split, offer, price);
     }
 
-    pub fn place_property_split_on_sale(&mut self, property_split_id: U128) {
+    // the split owner turns an offer down and the buyer gets the deposit back
+    pub fn reject_offer(&mut self, property_split_id: U128, offer_id: U128) {
 
-        let property_split = self.property_splits.get((property_split_id.clone().0 - 1) as u64).unwrap_or_else(|| env::panic_str("Split id does not exist"));
-        let token_id = property_split.token_id;
+        let property_split = self.property_splits.get((property_split_id.0 - 1) as u64).unwrap_or_else(|| env::panic_str("Split id does not exist"));
 
-        let mut split = self.property_split_by_token_id.get(&token_id).unwrap();
+        require!(env::signer_account_id() == self.get_split_seller(&property_split), "Not authorised to sell this property");
 
-        require!(if property_split.last_sale_date == 0 {
-            env::signer_account_id() == self.owner
-        } else {
-            env::signer_account_id() == property_split.owner
-        }, "Not authorised to sell this property");
+        let offer = self.get_offer(&property_split_id, &offer_id);
+
+        self.close_offer(&property_split_id, offer, OfferStatus::Rejected);
+    }
+
+    pub fn counter_offer(&mut self, property_split_id: U128, offer_id: U128, counter_price: U128) {
+
+        let property_split = self.property_splits.get((property_split_id.0 - 1) as u64).unwrap_or_else(|| env::panic_str("Split id does not exist"));
+
+        require!(env::signer_account_id() == self.get_split_seller(&property_split), "Not authorised to sell this property");
+
+        let mut offer = self.get_offer(&property_split_id, &offer_id);
+
+        require!(offer.is_open(), "Offer is no longer open");
+        require!(!offer.is_expired(), "Offer has expired");
+        require!(counter_price.0 > 0, "Counter price must be greater than zero");
+
+        offer.counter_price = Some(counter_price.0);
+        offer.set_status(OfferStatus::Countered, env::signer_account_id());
+
+        self.save_offer(&property_split_id, &offer);
+    }
+
+    // the buyer agrees to the counter price, topping up the deposit if needed, and the sale goes through
+    #[payable]
+    pub fn accept_counter_offer(&mut self, property_split_id: U128, offer_id: U128) {
+
+        self.internal_accept_counter_offer(property_split_id, offer_id, env::signer_account_id(), env::attached_deposit(), None);
+    }
+
+    fn internal_accept_counter_offer(&mut self, property_split_id: U128, offer_id: U128, buyer: AccountId, top_up: Balance, currency: Option<AccountId>) {
+
+        let property_split = self.property_splits.get((property_split_id.0 - 1) as u64).unwrap_or_else(|| env::panic_str("Split id does not exist"));
+
+        let mut offer = self.get_offer(&property_split_id, &offer_id);
+
+        require!(buyer == offer.buyer, "Not authorised to accept this offer");
+        require!(top_up == 0 || offer.currency == currency, "Top up must be paid in the offer's currency");
+        require!(offer.status == OfferStatus::Countered, "Offer has not been countered");
+        require!(!offer.is_expired(), "Offer has expired");
+
+        // a counter only binds the account that still owns the split
+        let countered_by = offer.history.last().unwrap().actor.clone();
+        require!(countered_by == self.get_split_seller(&property_split), "Counter offer is no longer valid");
+
+        let price = offer.counter_price.unwrap();
+
+        offer.value += top_up;
+
+        require!(offer.value >= price, "Not sufficient deposit to accept counter offer");
+
+        self.settle_offer(property_split, offer, price);
+    }
+
+    pub fn decline_counter_offer(&mut self, property_split_id: U128, offer_id: U128) {
+
+        let offer = self.get_offer(&property_split_id, &offer_id);
+
+        require!(env::signer_account_id() == offer.buyer, "Not authorised to decline this offer");
+        require!(offer.status == OfferStatus::Countered, "Offer has not been countered");
+
+        self.close_offer(&property_split_id, offer, OfferStatus::Declined);
+    }
+
+    pub fn cancel_offer(&mut self, property_split_id: U128, offer_id: U128) {
+
+        let offer = self.get_offer(&property_split_id, &offer_id);
+
+        require!(env::signer_account_id() == offer.buyer, "Not authorised to cancel this offer");
+
+        self.close_offer(&property_split_id, offer, OfferStatus::Cancelled);
+    }
+
+    // anyone can release an expired offer; the deposit always goes back to the buyer
+    pub fn reclaim_expired_offer(&mut self, property_split_id: U128, offer_id: U128) {
+
+        let offer = self.get_offer(&property_split_id, &offer_id);
+
+        require!(offer.is_expired(), "Offer has not expired");
+
+        self.close_offer(&property_split_id, offer, OfferStatus::Expired);
+    }
+
+    // pays out everything credited to the caller in one currency, NEAR when none is given
+    pub fn claim_refund(&mut self, currency: Option<AccountId>) -> U128 {
+
+        let account = env::signer_account_id();
+        let amount = self.refunds.remove(&(account.clone(), currency.clone())).unwrap_or(0);
+
+        require!(amount > 0, "No refund to claim");
+
+        send_payment(&currency, account.clone(), amount);
+
+        log!("Refund of {} claimed by {}", amount, account);
+
+        U128::from(amount)
+    }
+
+    // NEP-178 receiver: approving the marketplace on a split's token with a `SaleArgs` msg lists the
+    // split, or updates its listing when it is already on sale. An empty msg only lets the marketplace
+    // move the token, which selling the split through an offer, auction, bundle or issuer sale needs
+    pub fn nft_on_approve(&mut self, token_id: TokenId, owner_id: AccountId, approval_id: u64, msg: String) {
+
+        require!(env::predecessor_account_id().as_str() == NFT_CONTRACT, "Only the property token contract can list splits");
+
+        let mut split = self.property_split_by_token_id.get(&token_id).unwrap_or_else(|| env::panic_str("Token is not a property split"));
+
+        require!(owner_id == self.get_split_seller(&split), "Not authorised to sell this property");
+
+        self.split_approvals.insert(&split.id, &approval_id);
+
+        if msg.is_empty() {
+            log!("Split {} approved for sale by {}", split.id.0, owner_id);
+            return;
+        }
+
+        let sale: SaleArgs = near_sdk::serde_json::from_str(&msg).unwrap_or_else(|_| env::panic_str("Invalid sale message"));
+
+        require!(sale.price.0 > 0, "Sale price must be greater than zero");
+        require!(!self.is_split_locked(&split.id), "Split is already in an auction or bundle");
+
+        let starts_at = sale.starts_at.unwrap_or(env::block_timestamp_ms());
+
+        if let Some(ends_at) = sale.ends_at {
+            require!(ends_at > starts_at && ends_at > env::block_timestamp_ms(), "Sale must end after it starts and in the future");
+        }
+
+        if let Some(token) = &sale.currency {
+            require!(self.accepted_tokens.contains(token), "Token is not accepted");
+        }
 
         split.on_sale = true;
+        split.sale_price = sale.price.0;
+        split.sale_starts_at = starts_at;
+        split.sale_ends_at = sale.ends_at;
+        split.sale_currency = sale.currency;
+        split.sale_approval_id = Some(approval_id);
+
+        self.save_split(&split);
+
+        log!("Split {} listed by {} for {}", split.id.0, owner_id, sale.price.0);
+    }
+
+    // called by the token contract when the marketplace's approval on a split is revoked
+    pub fn nft_on_revoke(&mut self, token_id: TokenId, owner_id: AccountId) {
+
+        require!(env::predecessor_account_id().as_str() == NFT_CONTRACT, "Only the property token contract can delist splits");
+
+        let mut split = match self.property_split_by_token_id.get(&token_id) {
+            Some(split) => split,
+            None => return
+        };
+
+        if owner_id != self.get_split_seller(&split) {
+            return;
+        }
+
+        self.split_approvals.remove(&split.id);
+
+        if !split.on_sale {
+            return;
+        }
+
+        split.clear_listing();
+
+        self.save_split(&split);
+
+        log!("Split {} delisted after its approval was revoked", split.id.0);
+    }
+
+    pub fn set_sale_price(&mut self, property_split_id: U128, price: U128) {
+
+        let mut split = self.property_splits.get((property_split_id.0 - 1) as u64).unwrap_or_else(|| env::panic_str("Split id does not exist"));
 
-        self.property_split_by_token_id.insert(&token_id, &split);
+        require!(env::signer_account_id() == self.get_split_seller(&split), "Not authorised to sell this property");
+        require!(split.on_sale, "Property is not available for sale");
+        require!(price.0 > 0, "Sale price must be greater than zero");
 
-        self.property_splits.replace((property_split_id.clone().0 - 1) as u64, &split);
+        split.sale_price = price.0;
 
+        self.save_split(&split);
     }
 
+    pub fn delist_property_split(&mut self, property_split_id: U128) {
+
+        let mut split = self.property_splits.get((property_split_id.0 - 1) as u64).unwrap_or_else(|| env::panic_str("Split id does not exist"));
+
+        require!(env::signer_account_id() == self.get_split_seller(&split), "Not authorised to sell this property");
+        require!(split.on_sale, "Property is not available for sale");
+
+        split.clear_listing();
+
+        self.save_split(&split);
+
+        log!("Split {} delisted", property_split_id.0);
+    }
+
+    #[payable]
     pub fn buy_from_sale(&mut self, property_split_id: U128) {
 
         let property_split = self.property_splits.get((property_split_id.clone().0 - 1) as u64).unwrap_or_else(|| env::panic_str("Split id does not exist"));
 
         let buyer = env::signer_account_id();
 
-        require!(buyer != self.owner && buyer != property_split.owner, "Not authorized");
+        self.require_purchasable(&property_split, &buyer, &None);
 
-        require!(property_split.on_sale, "Property is not available for sale");
+        let price = property_split.sale_price;
 
-        require!(env::attached_deposit() >= self.get_split_value(&property_split_id), "Not sufficient deposit to make offer");
+        require!(env::attached_deposit() >= price, "Not sufficient deposit to buy property");
 
-        ext_nft_contract::ext(AccountId::new_unchecked(NFT_CONTRACT.to_string()))
-            .transfer_token(
-                property_split.token_id.clone(),
-                buyer.clone())
-            .then(
-                Self::ext(env::current_account_id())
-                    .with_static_gas(XCC_GAS)
-                    .on_transfer_token_callback_on_sale(property_split_id, &property_split.token_id, buyer) 
-            );
+        self.transfer_split_on_sale(&property_split, buyer, price, env::attached_deposit(), None, None);
     }
 
+    // buys several listed splits at once; each transfer settles on its own, so a failed transfer
+    // only refunds the price of that split
+    #[payable]
+    pub fn buy_many_from_sale(&mut self, property_split_ids: Vec<U128>) {
+
+        require!(!property_split_ids.is_empty(), "No splits to buy");
+        require!(property_split_ids.len() <= MAX_BATCH_PURCHASE, "Too many splits in one purchase");
+
+        let buyer = env::signer_account_id();
+
+        let mut splits: Vec<PropertySplit> = Vec::new();
+        let mut total: Balance = 0;
+
+        for property_split_id in property_split_ids.iter() {
+            let property_split = self.property_splits.get((property_split_id.0 - 1) as u64).unwrap_or_else(|| env::panic_str("Split id does not exist"));
+
+            require!(splits.iter().all(|split| split.id != property_split.id), "Split listed more than once");
+            self.require_purchasable(&property_split, &buyer, &None);
+
+            total += property_split.sale_price;
+            splits.push(property_split);
+        }
+
+        require!(env::attached_deposit() >= total, "Not sufficient deposit to buy properties");
+
+        for property_split in splits.iter() {
+            self.transfer_split_on_sale(property_split, buyer.clone(), property_split.sale_price, property_split.sale_price, None, None);
+        }
+
+        let refund = env::attached_deposit() - total;
 
-    // returns the value of a split based on the actual property valuation
+        if refund > 0 {
+            Promise::new(buyer).transfer(refund);
+        }
+    }
+
+
+    // returns the value of a split in yoctoNEAR based on the actual property valuation
     pub fn get_split_value(&self, property_split_id: &U128) -> Balance {
 
         let property_split = self.property_splits.get((property_split_id.clone().0 - 1) as u64).unwrap_or_else(|| env::panic_str("Split id does not exist"));
 
         let property = self.properties.get((property_split.property_id.0 - 1) as u64).unwrap();
 
-        let value = property.valuation;
+        let value = self.valuation_in_near(&property);
         let splits = u128::from(property.split_ids.len() as u64);
 
         let split_value = value*100/splits;
@@ -321,6 +966,16 @@ impl RietsAfrica {
     }
 
 
+    // the account entitled to the proceeds of a split sale; unsold splits belong to the contract owner
+    pub fn get_split_seller(&self, property_split: &PropertySplit) -> AccountId {
+        if property_split.last_sale_date == 0 {
+            self.owner.clone()
+        } else {
+            property_split.owner.clone()
+        }
+    }
+
+
     pub fn get_properties(&self) -> Vec<PropertyWithSplits> {
 
         self.properties.iter().map(|property| {
@@ -336,6 +991,7 @@ impl RietsAfrica {
                 image: property.image.clone(),
                 property_identifier: property.property_identifier.clone(),
                 valuation: property.valuation.clone(),
+                valuation_currency: property.valuation_currency.clone(),
                 property_splits: splits
             }
         }).collect::<Vec<PropertyWithSplits>>()
@@ -379,12 +1035,343 @@ impl RietsAfrica {
 
 
     pub fn get_splits_on_sale(&self) -> Vec<PropertySplit> {
-        self.property_splits.iter().filter(|split| split.on_sale).collect()
+        self.property_splits.iter().filter(|split| split.on_sale && !split.is_sale_expired()).collect()
+    }
+
+
+    pub fn get_property_fee(&self, property_id: U128) -> u16 {
+        self.property_fee_bps.get(&property_id).unwrap_or(self.platform_fee_bps)
+    }
+
+
+    pub fn get_treasury(&self) -> TreasuryInfo {
+        TreasuryInfo {
+            platform_fee_bps: self.platform_fee_bps,
+            balance: self.treasury,
+            fees_collected: self.fees_collected
+        }
+    }
+
+
+    pub fn get_token_treasury(&self, token_id: AccountId) -> TreasuryInfo {
+        TreasuryInfo {
+            platform_fee_bps: self.platform_fee_bps,
+            balance: self.token_treasury.get(&token_id).unwrap_or(0),
+            fees_collected: self.token_fees_collected.get(&token_id).unwrap_or(0)
+        }
+    }
+
+
+    pub fn get_claimable_refund(&self, account_id: AccountId, currency: Option<AccountId>) -> U128 {
+        U128::from(self.refunds.get(&(account_id, currency)).unwrap_or(0))
     }
 
 
+    pub fn get_failed_mints(&self, property_id: U128) -> Vec<String> {
+        self.failed_mints.get(&property_id).unwrap_or_default()
+    }
+
+
+    pub fn get_split_sales(&self, property_split_id: U128) -> Vec<SaleRecord> {
+        self.sales.get(&property_split_id).map(|sales| sales.iter().collect()).unwrap_or_default()
+    }
+
+
+    // splits held by an auction, a bundle or an unfinished primary sale cannot be sold any other way
+    fn is_split_locked(&self, property_split_id: &U128) -> bool {
+        self.active_auctions.get(property_split_id).is_some()
+            || self.bundled_splits.get(property_split_id).is_some()
+            || self.reserved_splits.contains(property_split_id)
+    }
+
+    // holds an issuer split for a Dutch auction or primary offering buyer until its transfer resolves
+    fn reserve_split(&mut self, pending_split_ids: &mut Vec<U128>, property_split_id: &U128) {
+        pending_split_ids.push(*property_split_id);
+        self.reserved_splits.insert(property_split_id);
+    }
+
+    // lets go of a split reserved in `pending_split_ids`; false if it was not held there
+    fn release_split(&mut self, pending_split_ids: &mut Vec<U128>, property_split_id: &U128) -> bool {
+        if !pending_split_ids.contains(property_split_id) {
+            return false;
+        }
+
+        pending_split_ids.retain(|split_id| split_id != property_split_id);
+        self.reserved_splits.remove(property_split_id);
+
+        true
+    }
+
+    // the marketplace can only move a split's token once its holder has approved it
+    fn require_split_approved(&self, property_split_id: &U128) {
+        require!(self.split_approvals.get(property_split_id).is_some(), "Approve the marketplace on the split's token first");
+    }
+
+    // splits still held by the issuer that the marketplace may move and that are not tied up in another
+    // sale; an expired listing no longer holds a split back
+    fn get_issuer_splits(&self, property_id: &U128) -> Vec<PropertySplit> {
+        let property = self.properties.get((property_id.0 - 1) as u64).unwrap();
+
+        property.split_ids.iter()
+            .map(|split_id| self.property_splits.get((split_id.0 - 1) as u64).unwrap())
+            .filter(|split| split.last_sale_date == 0 && (!split.on_sale || split.is_sale_expired()) && !self.is_split_locked(&split.id))
+            .filter(|split| self.split_approvals.get(&split.id).is_some())
+            .collect()
+    }
+
+    // keeps both split indexes in step
+    fn save_split(&mut self, split: &PropertySplit) {
+        self.property_split_by_token_id.insert(&split.token_id, split);
+        self.property_splits.replace((split.id.0 - 1) as u64, split);
+    }
+
+    fn get_offer(&self, property_split_id: &U128, offer_id: &U128) -> PurchaseOffer {
+        let offers_on_split = self.offers.get(property_split_id).unwrap_or_else(|| env::panic_str("No offer on this property"));
+
+        offers_on_split.get((offer_id.0 as u64) - 1).unwrap_or_else(|| env::panic_str("Offer id does not exist"))
+    }
+
+    fn save_offer(&mut self, property_split_id: &U128, offer: &PurchaseOffer) {
+        let mut offers_on_split = self.offers.get(property_split_id).unwrap();
+
+        offers_on_split.replace((offer.id.0 as u64) - 1, offer);
+        self.offers.insert(property_split_id, &offers_on_split);
+    }
+
+    // locks the offer as accepted and transfers the token; the deposit is settled in the callback
+    fn settle_offer(&mut self, property_split: PropertySplit, mut offer: PurchaseOffer, price: Balance) {
+
+        require!(!self.is_split_locked(&property_split.id), "Split is already in an auction or bundle");
+        self.require_split_approved(&property_split.id);
+
+        offer.set_status(OfferStatus::Accepted, env::signer_account_id());
+        self.save_offer(&property_split.id, &offer);
+        self.remove_open_offer(&property_split.id, &offer.id);
+
+        self.transfer_split_on_sale(&property_split, offer.buyer.clone(), price, offer.value, Some(offer.id), offer.currency.clone());
+    }
+
+    // moves the token to the buyer through NEP-199 so royalties are reported for the seller's proceeds
+    fn transfer_split_on_sale(&self, property_split: &PropertySplit, buyer: AccountId, price: Balance, deposit: Balance, offer_id: Option<U128>, currency: Option<AccountId>) {
+
+        let seller = self.get_split_seller(property_split);
+        let fee = price * u128::from(self.get_property_fee(property_split.property_id)) / u128::from(MAX_FEE_BPS);
+
+        // token payouts are function calls, so the callback needs gas for one per payout receiver and the
+        // refund, each with its resolve callback
+        let callback_gas = match currency {
+            Some(_) => XCC_GAS + Gas((FT_TRANSFER_GAS.0 + FT_RESOLVE_GAS.0) * u64::from(MAX_LEN_PAYOUT + 1)),
+            None => XCC_GAS
+        };
+
+        ext_nft_contract::ext(AccountId::new_unchecked(NFT_CONTRACT.to_string()))
+            .with_attached_deposit(1)
+            .nft_transfer_payout(
+                buyer.clone(),
+                property_split.token_id.clone(),
+                self.split_approvals.get(&property_split.id),
+                Some(format!("Sale of property split {}", property_split.id.0)),
+                U128::from(price - fee),
+                Some(MAX_LEN_PAYOUT))
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(callback_gas)
+                    .on_transfer_token_callback_on_sale(property_split.id, offer_id, SalePayment {
+                        seller,
+                        buyer,
+                        currency,
+                        price: U128::from(price),
+                        fee: U128::from(fee),
+                        deposit: U128::from(deposit)
+                    })
+            );
+    }
+
+    // marks an open offer as closed and refunds the buyer's deposit
+    fn close_offer(&mut self, property_split_id: &U128, mut offer: PurchaseOffer, status: OfferStatus) {
+        require!(offer.is_open(), "Offer is no longer open");
+
+        offer.set_status(status, env::signer_account_id());
+        self.save_offer(property_split_id, &offer);
+        self.remove_open_offer(property_split_id, &offer.id);
+
+        send_payment(&offer.currency, offer.buyer.clone(), offer.value);
+
+        log!("Offer {} on split {} closed, refunded {} to {}", offer.id.0, property_split_id.0, offer.value, offer.buyer);
+    }
+
+    fn credit_refund(&mut self, account: &AccountId, currency: &Option<AccountId>, amount: Balance) {
+        let key = (account.clone(), currency.clone());
+        let balance = self.refunds.get(&key).unwrap_or(0);
+        self.refunds.insert(&key, &(balance + amount));
+    }
+
+    // checks a listed split can be bought by `buyer` paying in `currency`
+    fn require_purchasable(&self, property_split: &PropertySplit, buyer: &AccountId, currency: &Option<AccountId>) {
+        require!(*buyer != self.owner && *buyer != property_split.owner, "Not authorized");
+        require!(property_split.is_sale_open(), "Property is not available for sale");
+        require!(property_split.sale_currency == *currency, "Property is priced in another currency");
+    }
+
+    fn record_sale(&mut self, sale: &SaleRecord) {
+        let mut sales_of_split = self.sales.get(&sale.property_split_id).unwrap_or_else(|| Vector::new(sales_prefix(&sale.property_split_id)));
+
+        sales_of_split.push(sale);
+        self.sales.insert(&sale.property_split_id, &sales_of_split);
+    }
+
+    fn remove_open_offer(&mut self, property_split_id: &U128, offer_id: &U128) {
+        let mut open_offers = self.open_offers.get(property_split_id).unwrap_or_default();
+
+        open_offers.retain(|open_offer_id| open_offer_id != offer_id);
+        self.open_offers.insert(property_split_id, &open_offers);
+    }
+
+    // credits every offer still open on a split to its buyer's claimable refund balance
+    fn refund_open_offers(&mut self, property_split_id: &U128) {
+        let mut offers_on_split = match self.offers.get(property_split_id) {
+            Some(offers) => offers,
+            None => return
+        };
+
+        let open_offers = self.open_offers.remove(property_split_id).unwrap_or_default();
+
+        if open_offers.is_empty() {
+            return;
+        }
+
+        for offer_id in open_offers.iter() {
+            let index = (offer_id.0 - 1) as u64;
+            let mut offer = offers_on_split.get(index).unwrap();
+
+            offer.set_status(OfferStatus::Refunded, env::current_account_id());
+            offers_on_split.replace(index, &offer);
+
+            self.credit_refund(&offer.buyer, &offer.currency, offer.value);
+        }
+
+        self.offers.insert(property_split_id, &offers_on_split);
+
+        // one line per split, since a receipt can only log so much and a bundle refunds several splits
+        log!(
+            "Offers {:?} on split {} refunded to their buyers",
+            open_offers.iter().map(|offer_id| offer_id.0).collect::<Vec<u128>>(),
+            property_split_id.0
+        );
+    }
+
+    // hands a split to its buyer once the token has moved and closes whatever was selling it
+    fn record_split_transfer(&mut self, property_split_id: &U128, buyer: &AccountId) -> u64 {
+        let mut split = self.property_splits.get((property_split_id.0 - 1) as u64).unwrap();
+
+        self.settle_split_income(&split);
+
+        split.owner = buyer.clone();
+        split.last_sale_date = env::block_timestamp_ms();
+        split.clear_listing();
+
+        self.save_split(&split);
+
+        // moving the token clears its approvals
+        self.split_approvals.remove(property_split_id);
+
+        self.refund_open_offers(property_split_id);
+        self.finish_auction(property_split_id, true);
+        self.finish_dutch_auction_sale(&split, true);
+        self.finish_offering_sale(&split, buyer, true);
+        self.finish_property_sale(&split, true);
+
+        split.last_sale_date
+    }
+
+    // settles the money side of a sale: the platform keeps its fee, the rest of the price goes to the
+    // royalty receivers and the seller, and the buyer gets back whatever was paid above it.
+    // Returns the royalties paid and the buyer's refund.
+    fn pay_out_sale(&mut self, payment: &SalePayment, reported_payout: HashMap<AccountId, U128>) -> (Balance, Balance) {
+        let SalePayment { seller, buyer, currency, price, fee, deposit } = payment;
+
+        let fee = fee.0;
+        let proceeds = price.0 - fee;
+        let refund = deposit.0 - price.0;
+
+        match currency {
+            Some(token) => {
+                self.token_treasury.insert(token, &(self.token_treasury.get(token).unwrap_or(0) + fee));
+                self.token_fees_collected.insert(token, &(self.token_fees_collected.get(token).unwrap_or(0) + fee));
+            }
+            None => {
+                self.treasury += fee;
+                self.fees_collected += fee;
+            }
+        }
+
+        let payout_total = reported_payout.values().fold(0u128, |total, amount| total.saturating_add(amount.0));
+
+        // a payout the proceeds cannot cover is ignored and the seller is paid in full
+        let mut payout = if reported_payout.len() > MAX_LEN_PAYOUT as usize || payout_total > proceeds {
+            HashMap::new()
+        } else {
+            reported_payout
+        };
+
+        let royalties = payout.iter().filter(|(account, _)| *account != seller).map(|(_, amount)| amount.0).sum::<u128>();
+        payout.insert(seller.clone(), U128::from(proceeds - royalties));
+
+        for (account, amount) in payout {
+            if amount.0 > 0 {
+                send_payment(currency, account, amount.0);
+            }
+        }
+
+        if refund > 0 {
+            send_payment(currency, buyer.clone(), refund);
+        }
+
+        (royalties, refund)
+    }
+
     #[private]
-    pub fn on_mint_nft_callback(&mut self, property_id: U128, split_identifier: String, #[callback_unwrap] token: Token) {
+    pub fn on_mint_nft_callback(
+        &mut self,
+        property_id: U128,
+        split_identifier: String,
+        purchaser: Option<AccountId>,
+        price: U128,
+        #[callback_result] call_result: Result<Token, PromiseError>
+    ) {
+
+        let token = match call_result {
+            Ok(token) => token,
+            Err(_) => {
+                let mut failed = self.failed_mints.get(&property_id).unwrap_or_default();
+                failed.push(split_identifier.clone());
+                self.failed_mints.insert(&property_id, &failed);
+
+                // a purchaser who paid for a split that was never minted gets the money back
+                if let Some(purchaser) = purchaser {
+                    self.credit_refund(&purchaser, &None, price.0);
+                }
+
+                log!("Minting split {} of property {} failed", split_identifier, property_id.0);
+                return;
+            }
+        };
+
+        // a split minted straight to a purchaser counts as sold, and the issuer is paid for it less the
+        // same fee a sale of the split would pay
+        let last_sale_date = match purchaser {
+            Some(_) => {
+                let fee = price.0 * u128::from(self.get_property_fee(property_id)) / u128::from(MAX_FEE_BPS);
+
+                self.treasury += fee;
+                self.fees_collected += fee;
+
+                Promise::new(self.owner.clone()).transfer(price.0 - fee);
+                env::block_timestamp_ms()
+            }
+            None => 0
+        };
+
         let splits_count = self.property_splits.len();
         let split_id = U128::from(u128::from(splits_count) + 1);
 
@@ -396,9 +1383,14 @@ impl RietsAfrica {
             property_id: property_id.clone(),
             token_id: token_minted.token_id,
             token_metadata: token_minted.metadata.unwrap(),
-            owner: env::signer_account_id(),
-            last_sale_date: 0,
-            on_sale: false
+            owner: token_minted.owner_id,
+            last_sale_date,
+            on_sale: false,
+            sale_price: 0,
+            sale_starts_at: 0,
+            sale_ends_at: None,
+            sale_currency: None,
+            sale_approval_id: None
         };
 
         self.property_split_by_token_id.insert(&token.token_id, &property_split);
@@ -409,30 +1401,349 @@ impl RietsAfrica {
 
         self.properties.replace((property_id.0 - 1) as u64, &prop);
 
+        self.start_split_income(&property_id, &split_id);
+
         self.property_splits.push(&property_split);
 
     }
 
     #[private]
-    pub fn on_transfer_token_callback_on_sale(&mut self, property_split_id: U128, property_token_id: &TokenId, buyer: AccountId) {
+    pub fn on_transfer_token_callback_on_sale(
+        &mut self,
+        property_split_id: U128,
+        offer_id: Option<U128>,
+        payment: SalePayment,
+        #[callback_result] call_result: Result<Payout, PromiseError>
+    ) {
+
+        let reported_payout = match call_result {
+            Ok(payout) => payout.payout,
+            Err(_) => {
+                // the token never moved, so ownership stays as is and the buyer gets the full deposit back
+                if let Some(offer_id) = offer_id {
+                    let mut offer = self.get_offer(&property_split_id, &offer_id);
+
+                    offer.set_status(OfferStatus::Refunded, env::current_account_id());
+                    self.save_offer(&property_split_id, &offer);
+                }
+
+                send_payment(&payment.currency, payment.buyer.clone(), payment.deposit.0);
+
+                let property_split = self.property_splits.get((property_split_id.0 - 1) as u64).unwrap();
+
+                self.finish_auction(&property_split_id, false);
+                self.finish_dutch_auction_sale(&property_split, false);
+                self.finish_offering_sale(&property_split, &payment.buyer, false);
+                self.finish_property_sale(&property_split, false);
+
+                log!("Transfer of split {} to {} failed, refunded {}", property_split_id.0, payment.buyer, payment.deposit.0);
+                return;
+            }
+        };
 
-        let property_split = self.property_splits.get((property_split_id.0 - 1) as u64).unwrap();
-        let token_id = property_split.token_id;
-        let mut split = self.property_split_by_token_id.get(property_token_id).unwrap();
+        let sale_date = self.record_split_transfer(&property_split_id, &payment.buyer);
 
-        split.owner = buyer;
-        split.last_sale_date = env::block_timestamp_ms();
-        split.on_sale = false;
-        
-        self.property_split_by_token_id.insert(property_token_id, &split);
-
-        let empty = Vector::new(b"l");
+        let (royalties, refund) = self.pay_out_sale(&payment, reported_payout);
 
-        self.offers.insert(&property_split.id, &empty);
+        log!("Split {} sold by {} to {} for {}", property_split_id.0, payment.seller, payment.buyer, payment.price.0);
 
-        self.property_splits.replace((property_split_id.0 - 1) as u64, &split);
+        self.record_sale(&SaleRecord {
+            property_split_id,
+            seller: payment.seller,
+            buyer: payment.buyer,
+            currency: payment.currency,
+            price: payment.price.0,
+            fee: payment.fee.0,
+            royalties,
+            refund,
+            bundle_id: None,
+            sale_date
+        });
     }
 
     
 }
 
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use near_sdk::mock::VmAction;
+    use near_sdk::test_utils::{get_created_receipts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    pub(crate) const MARKET: &str = "market.testnet";
+    pub(crate) const OWNER: &str = "owner.testnet";
+    pub(crate) const BUYER: &str = "alice.testnet";
+    const ARTIST: &str = "artist.testnet";
+
+    pub(crate) fn account(id: &str) -> AccountId {
+        id.parse().unwrap()
+    }
+
+    pub(crate) fn set_context(signer: &str, deposit: Balance) {
+        set_context_at(signer, deposit, 0);
+    }
+
+    // `now` is the block time in milliseconds
+    pub(crate) fn set_context_at(signer: &str, deposit: Balance, now: u64) {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(account(MARKET))
+            .signer_account_id(account(signer))
+            .predecessor_account_id(account(signer))
+            .attached_deposit(deposit)
+            .block_timestamp(now * 1_000_000)
+            .build());
+    }
+
+    pub(crate) fn minted_token(token_id: &str, owner: &str) -> Token {
+        Token {
+            token_id: token_id.to_string(),
+            owner_id: account(owner),
+            metadata: Some(TokenMetadata {
+                title: None,
+                description: None,
+                media: None,
+                media_hash: None,
+                copies: None,
+                issued_at: None,
+                expires_at: None,
+                starts_at: None,
+                updated_at: None,
+                extra: None,
+                reference: None,
+                reference_hash: None
+            }),
+            approved_account_ids: None
+        }
+    }
+
+    // a marketplace charging 250 basis points, with one property of two splits valued at 50,000 each
+    pub(crate) fn setup() -> RietsAfrica {
+        set_context(OWNER, 0);
+
+        let mut contract = RietsAfrica::default();
+        contract.set_platform_fee(250);
+        contract.create_property(PropertyArgs {
+            name: "Lekki Gardens".to_string(),
+            image_url: "https://example.com/lekki.png".to_string(),
+            identifier: "LKG".to_string(),
+            valuation: U128(100_000),
+            valuation_currency: None,
+            doc_urls: vec!["https://example.com/1.pdf".to_string(), "https://example.com/2.pdf".to_string()],
+            royalty: None
+        });
+
+        for token_id in ["1", "2"] {
+            contract.on_mint_nft_callback(U128(1), format!("LKG000{}", token_id), None, U128(0), Ok(minted_token(token_id, OWNER)));
+        }
+
+        contract
+    }
+
+    // the holder lets the marketplace move the split's token without listing it
+    pub(crate) fn approve(contract: &mut RietsAfrica, token_id: &str, holder: &str) {
+        set_context(NFT_CONTRACT, 0);
+        contract.nft_on_approve(token_id.to_string(), account(holder), 1, String::new());
+    }
+
+    pub(crate) fn payment(buyer: &str, price: Balance, deposit: Balance) -> SalePayment {
+        SalePayment {
+            seller: account(OWNER),
+            buyer: account(buyer),
+            currency: None,
+            price: U128(price),
+            fee: U128(price * 250 / 10000),
+            deposit: U128(deposit)
+        }
+    }
+
+    pub(crate) fn payout(shares: &[(&str, Balance)]) -> Payout {
+        Payout { payout: shares.iter().map(|(holder, amount)| (account(holder), U128(*amount))).collect() }
+    }
+
+    // NEAR sent by the last call, by receiver
+    pub(crate) fn transfers() -> HashMap<String, Balance> {
+        get_created_receipts().into_iter()
+            .flat_map(|receipt| {
+                let receiver = receipt.receiver_id.to_string();
+
+                receipt.actions.into_iter().filter_map(move |action| match action {
+                    VmAction::Transfer { deposit } => Some((receiver.clone(), deposit)),
+                    _ => None
+                })
+            })
+            .collect()
+    }
+
+    // the functions called by the last call, in order
+    pub(crate) fn function_calls() -> Vec<String> {
+        get_created_receipts().into_iter()
+            .flat_map(|receipt| receipt.actions)
+            .filter_map(|action| match action {
+                VmAction::FunctionCall { function_name, .. } => Some(function_name),
+                _ => None
+            })
+            .collect()
+    }
+
+    #[test]
+    fn settled_sale_pays_seller_royalties_fee_and_refund() {
+        let mut contract = setup();
+
+        set_context(MARKET, 0);
+        contract.on_transfer_token_callback_on_sale(
+            U128(1),
+            None,
+            payment(BUYER, 100_000, 110_000),
+            Ok(payout(&[(ARTIST, 9_750), (OWNER, 87_750)]))
+        );
+
+        let paid = transfers();
+        assert_eq!(paid[OWNER], 87_750);
+        assert_eq!(paid[ARTIST], 9_750);
+        assert_eq!(paid[BUYER], 10_000);
+
+        assert_eq!(contract.treasury, 2_500);
+        assert_eq!(contract.fees_collected, 2_500);
+
+        assert_eq!(contract.property_splits.get(0).unwrap().owner, account(BUYER));
+
+        let sale = &contract.get_split_sales(U128(1))[0];
+        assert_eq!(sale.buyer, account(BUYER));
+        assert_eq!(sale.royalties, 9_750);
+        assert_eq!(sale.refund, 10_000);
+    }
+
+    #[test]
+    fn payout_above_the_proceeds_pays_the_seller_in_full() {
+        let mut contract = setup();
+
+        set_context(MARKET, 0);
+        contract.on_transfer_token_callback_on_sale(
+            U128(1),
+            None,
+            payment(BUYER, 100_000, 100_000),
+            Ok(payout(&[(ARTIST, 50_000), (OWNER, 50_000)]))
+        );
+
+        let paid = transfers();
+        assert_eq!(paid[OWNER], 97_500);
+        assert!(!paid.contains_key(ARTIST));
+        assert_eq!(contract.get_split_sales(U128(1))[0].royalties, 0);
+    }
+
+    #[test]
+    fn failed_transfer_refunds_the_buyer_and_keeps_the_split() {
+        let mut contract = setup();
+
+        set_context(BUYER, 60_000);
+        contract.make_property_offer(U128(1), None);
+
+        set_context(MARKET, 0);
+        contract.on_transfer_token_callback_on_sale(
+            U128(1),
+            Some(U128(1)),
+            payment(BUYER, 60_000, 60_000),
+            Err(PromiseError::Failed)
+        );
+
+        assert_eq!(transfers()[BUYER], 60_000);
+
+        assert!(contract.get_offer(&U128(1), &U128(1)).status == OfferStatus::Refunded);
+        assert_eq!(contract.property_splits.get(0).unwrap().owner, account(OWNER));
+        assert!(contract.get_split_sales(U128(1)).is_empty());
+        assert_eq!(contract.treasury, 0);
+    }
+
+    #[test]
+    fn sale_after_many_cancelled_offers_only_visits_open_ones() {
+        let mut contract = setup();
+
+        for _ in 0..200 {
+            set_context("mallory.testnet", 50_000);
+            contract.make_property_offer(U128(1), None);
+
+            let offer_id = contract.get_split_offers(U128(1)).last().unwrap().id;
+            contract.cancel_offer(U128(1), offer_id);
+        }
+
+        for index in 0..MAX_OPEN_OFFERS {
+            set_context(&format!("bidder{}.testnet", index), 50_000);
+            contract.make_property_offer(U128(1), None);
+        }
+
+        set_context(MARKET, 0);
+        contract.on_transfer_token_callback_on_sale(
+            U128(1),
+            None,
+            payment(BUYER, 100_000, 100_000),
+            Ok(payout(&[(OWNER, 97_500)]))
+        );
+
+        assert!(env::used_gas() < XCC_GAS);
+
+        assert_eq!(contract.get_claimable_refund(account("bidder0.testnet"), None).0, 50_000);
+        assert_eq!(contract.get_claimable_refund(account("mallory.testnet"), None).0, 0);
+        assert!(contract.open_offers.get(&U128(1)).unwrap_or_default().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Too many open offers on this split")]
+    fn open_offers_on_a_split_are_capped() {
+        let mut contract = setup();
+
+        for index in 0..=MAX_OPEN_OFFERS {
+            set_context(&format!("bidder{}.testnet", index), 50_000);
+            contract.make_property_offer(U128(1), None);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Approve the marketplace on the split's token first")]
+    fn offer_on_a_split_the_marketplace_cannot_move_cannot_be_accepted() {
+        let mut contract = setup();
+
+        set_context(BUYER, 50_000);
+        contract.make_property_offer(U128(1), None);
+
+        set_context(OWNER, 0);
+        contract.sell_property_to_offer(U128(1), U128(1));
+    }
+
+    #[test]
+    fn accepted_offer_moves_the_token_with_the_holders_approval() {
+        let mut contract = setup();
+
+        approve(&mut contract, "1", OWNER);
+
+        set_context(BUYER, 50_000);
+        contract.make_property_offer(U128(1), None);
+
+        set_context(OWNER, 0);
+        contract.sell_property_to_offer(U128(1), U128(1));
+
+        assert_eq!(function_calls(), vec!["nft_transfer_payout", "on_transfer_token_callback_on_sale"]);
+        assert!(!contract.get_split_offers(U128(1))[0].is_open());
+    }
+
+    #[test]
+    fn revoked_or_transferred_splits_lose_their_approval() {
+        let mut contract = setup();
+
+        approve(&mut contract, "1", OWNER);
+        approve(&mut contract, "2", OWNER);
+
+        assert_eq!(contract.get_issuer_splits(&U128(1)).len(), 2);
+
+        set_context(NFT_CONTRACT, 0);
+        contract.nft_on_revoke("1".to_string(), account(OWNER));
+
+        set_context(MARKET, 0);
+        contract.on_transfer_token_callback_on_sale(U128(2), None, payment(BUYER, 50_000, 50_000), Ok(payout(&[(OWNER, 48_750)])));
+
+        assert!(contract.split_approvals.get(&U128(1)).is_none());
+        assert!(contract.split_approvals.get(&U128(2)).is_none());
+        assert!(contract.get_issuer_splits(&U128(1)).is_empty());
+    }
+}
@@ -0,0 +1,155 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::{env, near_bindgen, AccountId, Balance, Promise, require};
+
+use crate::{is_issuer_sale_running, PropertySplit, RietsAfrica, RietsAfricaExt, MAX_BATCH_PURCHASE};
+
+
+#[derive(Clone, BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PrimaryOffering {
+    property_id: U128,
+    price: Balance,
+    // block timestamps in milliseconds
+    opens_at: u64,
+    closes_at: u64,
+    // how many splits one buyer must and may hold from the offering
+    min_per_buyer: u64,
+    max_per_buyer: u64,
+    // splits whose transfer to a buyer has not resolved yet
+    pending_split_ids: Vec<U128>,
+    splits_sold: u64
+}
+
+impl PrimaryOffering {
+    pub fn is_in_window(&self) -> bool {
+        let now = env::block_timestamp_ms();
+        now >= self.opens_at && now < self.closes_at
+    }
+}
+
+
+#[near_bindgen]
+impl RietsAfrica {
+
+    pub fn start_primary_offering(&mut self, property_id: U128, price: U128, opens_at: Option<u64>, closes_at: u64, min_per_buyer: u64, max_per_buyer: u64) {
+
+        require!(env::signer_account_id() == self.owner, "Not authorised");
+        self.properties.get((property_id.0 - 1) as u64).unwrap_or_else(|| env::panic_str("Property id does not exist"));
+
+        require!(!self.has_open_primary_offering(&property_id), "Property already has a running primary offering");
+        require!(!self.has_open_dutch_auction(&property_id), "Property already has a running Dutch auction");
+
+        let opens_at = opens_at.unwrap_or(env::block_timestamp_ms());
+
+        require!(closes_at > opens_at && closes_at > env::block_timestamp_ms(), "Offering must close after it opens and in the future");
+        require!(price.0 > 0, "Sale price must be greater than zero");
+        require!(min_per_buyer > 0 && min_per_buyer <= max_per_buyer, "Minimum per buyer must be between one and the maximum");
+
+        let offering = PrimaryOffering {
+            property_id,
+            price: price.0,
+            opens_at,
+            closes_at,
+            min_per_buyer,
+            max_per_buyer,
+            pending_split_ids: Vec::new(),
+            splits_sold: 0
+        };
+
+        self.primary_offerings.insert(&property_id, &offering);
+    }
+
+    // buys `count` splits from the issuer's inventory at the offering price
+    #[payable]
+    pub fn buy_from_primary_offering(&mut self, property_id: U128, count: u64) -> Vec<U128> {
+
+        let mut offering = self.primary_offerings.get(&property_id).unwrap_or_else(|| env::panic_str("Property has no primary offering"));
+
+        let buyer = env::signer_account_id();
+
+        require!(buyer != self.owner, "Not authorized");
+        require!(offering.is_in_window(), "Primary offering is not open");
+        require!(count > 0 && count as usize <= MAX_BATCH_PURCHASE, "Too many splits in one purchase");
+
+        let held = self.offering_purchases.get(&(property_id, buyer.clone())).unwrap_or(0);
+
+        require!(held + count >= offering.min_per_buyer, "Purchase is below the minimum per buyer");
+        require!(held + count <= offering.max_per_buyer, "Purchase exceeds the maximum per buyer");
+
+        let total = offering.price * u128::from(count);
+
+        require!(env::attached_deposit() >= total, "Not sufficient deposit to buy properties");
+
+        let splits: Vec<PropertySplit> = self.get_issuer_splits(&property_id).into_iter().take(count as usize).collect();
+
+        require!(splits.len() as u64 == count, "Not enough splits left in this offering");
+
+        for property_split in splits.iter() {
+            self.reserve_split(&mut offering.pending_split_ids, &property_split.id);
+        }
+
+        self.primary_offerings.insert(&property_id, &offering);
+        self.offering_purchases.insert(&(property_id, buyer.clone()), &(held + count));
+
+        for property_split in splits.iter() {
+            self.transfer_split_on_sale(property_split, buyer.clone(), offering.price, offering.price, None, None);
+        }
+
+        let refund = env::attached_deposit() - total;
+
+        if refund > 0 {
+            Promise::new(buyer).transfer(refund);
+        }
+
+        splits.into_iter().map(|split| split.id).collect()
+    }
+
+    pub fn get_primary_offering(&self, property_id: U128) -> Option<PrimaryOffering> {
+        self.primary_offerings.get(&property_id)
+    }
+
+    // the offering closes on its own once this reaches zero
+    pub fn get_primary_offering_remaining(&self, property_id: U128) -> u64 {
+        match self.primary_offerings.get(&property_id) {
+            Some(offering) if offering.is_in_window() => self.get_issuer_splits(&property_id).len() as u64,
+            _ => 0
+        }
+    }
+
+    pub fn get_primary_offering_purchases(&self, property_id: U128, account_id: AccountId) -> u64 {
+        self.offering_purchases.get(&(property_id, account_id)).unwrap_or(0)
+    }
+
+    pub(crate) fn has_open_primary_offering(&self, property_id: &U128) -> bool {
+        match self.primary_offerings.get(property_id) {
+            Some(offering) => is_issuer_sale_running(offering.closes_at, &offering.pending_split_ids),
+            None => false
+        }
+    }
+
+    // releases a split reserved by the property's primary offering once its transfer has resolved;
+    // a failed transfer no longer counts towards the buyer's cap
+    pub(crate) fn finish_offering_sale(&mut self, property_split: &PropertySplit, buyer: &AccountId, transferred: bool) {
+
+        let mut offering = match self.primary_offerings.get(&property_split.property_id) {
+            Some(offering) => offering,
+            None => return
+        };
+
+        if !self.release_split(&mut offering.pending_split_ids, &property_split.id) {
+            return;
+        }
+
+        if transferred {
+            offering.splits_sold += 1;
+        } else {
+            let key = (property_split.property_id, buyer.clone());
+            let held = self.offering_purchases.get(&key).unwrap_or(0);
+            self.offering_purchases.insert(&key, &held.saturating_sub(1));
+        }
+
+        self.primary_offerings.insert(&property_split.property_id, &offering);
+    }
+}
@@ -0,0 +1,129 @@
+use near_contract_standards::fungible_token::core::ext_ft_core;
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, log, near_bindgen, serde_json, AccountId, Balance, Promise, PromiseError, PromiseOrValue, require};
+
+use crate::{RietsAfrica, RietsAfricaExt, FT_RESOLVE_GAS, FT_TRANSFER_GAS};
+
+
+// what a buyer wants done with the tokens sent through `ft_transfer_call`
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde", tag = "action", rename_all = "snake_case")]
+pub enum FtPaymentMessage {
+    Buy { property_split_id: U128 },
+    Offer { property_split_id: U128, expires_at: Option<u64> },
+    AcceptCounterOffer { property_split_id: U128, offer_id: U128 }
+}
+
+// pays `amount` in NEAR, or in the given NEP-141 token. A token transfer that fails, e.g. because the
+// receiver is not registered with the token, is credited to the receiver's claimable refund balance
+pub(crate) fn send_payment(currency: &Option<AccountId>, receiver: AccountId, amount: Balance) {
+    pay(currency, receiver, amount, false);
+}
+
+// pays out of the treasury; a token transfer that fails goes back to the token treasury
+pub(crate) fn send_treasury_payment(currency: &Option<AccountId>, receiver: AccountId, amount: Balance) {
+    pay(currency, receiver, amount, true);
+}
+
+fn pay(currency: &Option<AccountId>, receiver: AccountId, amount: Balance, from_treasury: bool) {
+    match currency {
+        Some(token) => {
+            ext_ft_core::ext(token.clone())
+                .with_attached_deposit(1)
+                .with_static_gas(FT_TRANSFER_GAS)
+                .ft_transfer(receiver.clone(), U128::from(amount), None)
+            .then(
+                RietsAfrica::ext(env::current_account_id())
+                    .with_static_gas(FT_RESOLVE_GAS)
+                    .on_payment_resolved(token.clone(), receiver, U128::from(amount), from_treasury)
+            );
+        }
+        None => {
+            Promise::new(receiver).transfer(amount);
+        }
+    }
+}
+
+
+#[near_bindgen]
+impl RietsAfrica {
+
+    pub fn add_accepted_token(&mut self, token_id: AccountId) {
+        require!(env::signer_account_id() == self.owner, "Not authorised");
+
+        self.accepted_tokens.insert(&token_id);
+    }
+
+    // listings and offers already in the removed token still settle in it
+    pub fn remove_accepted_token(&mut self, token_id: AccountId) {
+        require!(env::signer_account_id() == self.owner, "Not authorised");
+
+        self.accepted_tokens.remove(&token_id);
+    }
+
+    pub fn is_accepted_token(&self, token_id: AccountId) -> bool {
+        self.accepted_tokens.contains(&token_id)
+    }
+
+    #[private]
+    pub fn on_payment_resolved(
+        &mut self,
+        token_id: AccountId,
+        receiver: AccountId,
+        amount: U128,
+        from_treasury: bool,
+        #[callback_result] call_result: Result<(), PromiseError>
+    ) {
+
+        if call_result.is_ok() {
+            return;
+        }
+
+        if from_treasury {
+            let balance = self.token_treasury.get(&token_id).unwrap_or(0);
+            self.token_treasury.insert(&token_id, &(balance + amount.0));
+        } else {
+            self.credit_refund(&receiver, &Some(token_id.clone()), amount.0);
+        }
+
+        log!("Payment of {} {} to {} failed and was credited back", amount.0, token_id, receiver);
+    }
+
+    // NEP-141 receiver; `msg` is a JSON `FtPaymentMessage`. Returns the amount the token contract
+    // should send back to the buyer
+    pub fn ft_on_transfer(&mut self, sender_id: AccountId, amount: U128, msg: String) -> PromiseOrValue<U128> {
+
+        let currency = env::predecessor_account_id();
+
+        require!(self.accepted_tokens.contains(&currency), "Token is not accepted");
+
+        let message: FtPaymentMessage = serde_json::from_str(&msg).unwrap_or_else(|_| env::panic_str("Invalid payment message"));
+
+        match message {
+            FtPaymentMessage::Buy { property_split_id } => {
+                let property_split = self.property_splits.get((property_split_id.0 - 1) as u64).unwrap_or_else(|| env::panic_str("Split id does not exist"));
+
+                self.require_purchasable(&property_split, &sender_id, &Some(currency.clone()));
+
+                let price = property_split.sale_price;
+
+                require!(amount.0 >= price, "Not sufficient deposit to buy property");
+
+                self.transfer_split_on_sale(&property_split, sender_id, price, price, None, Some(currency));
+
+                PromiseOrValue::Value(U128::from(amount.0 - price))
+            }
+            FtPaymentMessage::Offer { property_split_id, expires_at } => {
+                self.internal_make_offer(property_split_id, sender_id, amount.0, Some(currency), expires_at);
+
+                PromiseOrValue::Value(U128::from(0))
+            }
+            FtPaymentMessage::AcceptCounterOffer { property_split_id, offer_id } => {
+                self.internal_accept_counter_offer(property_split_id, offer_id, sender_id, amount.0, Some(currency));
+
+                PromiseOrValue::Value(U128::from(0))
+            }
+        }
+    }
+}
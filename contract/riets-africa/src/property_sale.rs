@@ -0,0 +1,278 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::{env, log, near_bindgen, AccountId, Balance, Promise, require};
+
+use crate::{PropertySplit, RietsAfrica, RietsAfricaExt};
+
+
+// a sale of the whole property to one buyer, approved by its holders through a proposal
+#[derive(Clone, BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PropertySale {
+    proposal_id: U128,
+    buyer: AccountId,
+    split_price: Balance,
+    // block timestamp in milliseconds after which no more splits can be tendered
+    closes_at: u64,
+    // paid in by the buyer and not yet spent on tendered splits
+    escrow: Balance,
+    // splits whose transfer to the buyer has not resolved yet
+    pending_split_ids: Vec<U128>,
+    splits_sold: u64
+}
+
+impl PropertySale {
+    pub fn is_open(&self) -> bool {
+        env::block_timestamp_ms() < self.closes_at
+    }
+}
+
+
+#[near_bindgen]
+impl RietsAfrica {
+
+    // the buyer pays for the property into escrow, in one go or over several calls, up to the price of
+    // the splits it does not hold yet
+    #[payable]
+    pub fn fund_property_sale(&mut self, property_id: U128) -> U128 {
+
+        let mut sale = self.property_sales.get(&property_id).unwrap_or_else(|| env::panic_str("Property has no approved sale"));
+
+        require!(env::signer_account_id() == sale.buyer, "Not authorised");
+        require!(sale.is_open(), "Property sale is closed");
+
+        let property = self.properties.get((property_id.0 - 1) as u64).unwrap();
+        let outstanding = property.split_ids.len() as u64 - sale.splits_sold - sale.pending_split_ids.len() as u64;
+
+        sale.escrow += env::attached_deposit();
+
+        require!(sale.escrow <= sale.split_price * u128::from(outstanding), "Deposit exceeds the price of the splits left to buy");
+
+        self.property_sales.insert(&property_id, &sale);
+
+        U128::from(sale.escrow)
+    }
+
+    // a holder sells their split to the buyer at the approved price, paid from the escrow. The marketplace
+    // moves the token, so it must be approved for the split on the token contract first
+    pub fn tender_split_to_property_sale(&mut self, property_split_id: U128) {
+
+        let property_split = self.property_splits.get((property_split_id.0 - 1) as u64).unwrap_or_else(|| env::panic_str("Split id does not exist"));
+
+        let mut sale = self.property_sales.get(&property_split.property_id).unwrap_or_else(|| env::panic_str("Property has no approved sale"));
+
+        let seller = env::signer_account_id();
+
+        require!(sale.is_open(), "Property sale is closed");
+        require!(seller == self.get_split_seller(&property_split), "Not authorised to sell this property");
+        require!(seller != sale.buyer, "Split is already held by the buyer");
+        require!(!self.is_split_locked(&property_split_id), "Split is already in an auction or bundle");
+        self.require_split_approved(&property_split_id);
+        require!(sale.escrow >= sale.split_price, "Buyer has not paid for this split");
+
+        sale.escrow -= sale.split_price;
+        self.reserve_split(&mut sale.pending_split_ids, &property_split_id);
+        self.property_sales.insert(&property_split.property_id, &sale);
+
+        self.transfer_split_on_sale(&property_split, sale.buyer.clone(), sale.split_price, sale.split_price, None, None);
+    }
+
+    // once the sale has closed the buyer takes back whatever no split was tendered for
+    pub fn withdraw_property_sale_escrow(&mut self, property_id: U128) -> U128 {
+
+        let mut sale = self.property_sales.get(&property_id).unwrap_or_else(|| env::panic_str("Property has no approved sale"));
+
+        require!(env::signer_account_id() == sale.buyer, "Not authorised");
+        require!(!sale.is_open(), "Property sale is still open");
+        require!(sale.escrow > 0, "Nothing to withdraw");
+
+        let amount = sale.escrow;
+
+        sale.escrow = 0;
+        self.property_sales.insert(&property_id, &sale);
+
+        Promise::new(sale.buyer.clone()).transfer(amount);
+
+        U128::from(amount)
+    }
+
+    pub fn get_property_sale(&self, property_id: U128) -> Option<PropertySale> {
+        self.property_sales.get(&property_id)
+    }
+
+    // opens the sale a passed `ApproveSale` proposal agreed to, for `duration_ms` from now
+    pub(crate) fn internal_open_property_sale(&mut self, property_id: &U128, proposal_id: U128, buyer: AccountId, split_price: Balance, duration_ms: u64) {
+
+        if let Some(sale) = self.property_sales.get(property_id) {
+            require!(!sale.is_open() && sale.pending_split_ids.is_empty(), "Property already has a running sale");
+
+            // escrow the earlier buyer never withdrew stays theirs to claim
+            if sale.escrow > 0 {
+                self.credit_refund(&sale.buyer, &None, sale.escrow);
+            }
+        }
+
+        let sale = PropertySale {
+            proposal_id,
+            buyer: buyer.clone(),
+            split_price,
+            closes_at: env::block_timestamp_ms() + duration_ms,
+            escrow: 0,
+            pending_split_ids: Vec::new(),
+            splits_sold: 0
+        };
+
+        self.property_sales.insert(property_id, &sale);
+
+        log!("Sale of property {} to {} opened at {} per split", property_id.0, buyer, split_price);
+    }
+
+    // releases a split tendered to the property's sale once its transfer has resolved; a failed transfer
+    // has already refunded its price to the buyer
+    pub(crate) fn finish_property_sale(&mut self, property_split: &PropertySplit, transferred: bool) {
+
+        let mut sale = match self.property_sales.get(&property_split.property_id) {
+            Some(sale) => sale,
+            None => return
+        };
+
+        if !self.release_split(&mut sale.pending_split_ids, &property_split.id) {
+            return;
+        }
+
+        if transferred {
+            sale.splits_sold += 1;
+        }
+
+        self.property_sales.insert(&property_split.property_id, &sale);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{account, approve, function_calls, payout, set_context, set_context_at, setup, transfers, BUYER, MARKET, OWNER};
+    use crate::{ProposalAction, ProposalArgs, SalePayment, DEFAULT_TIMELOCK_MS};
+    use near_sdk::PromiseError;
+
+    const VOTING_ENDS: u64 = 1_000;
+    const EXECUTED: u64 = VOTING_ENDS + DEFAULT_TIMELOCK_MS;
+    const DURATION: u64 = 5_000;
+    // covers the storage of a proposal or a vote, the rest is refunded
+    const STORAGE: Balance = 10_000_000_000_000_000_000_000;
+
+    // the holders of the setup property vote through a sale of it to the buyer at 60,000 a split
+    fn approved_sale() -> RietsAfrica {
+        let mut contract = setup();
+
+        set_context(OWNER, STORAGE);
+        let proposal_id = contract.create_proposal(ProposalArgs {
+            property_id: U128(1),
+            title: "Sell Lekki Gardens".to_string(),
+            description: String::new(),
+            options: vec!["Sell".to_string(), "Keep".to_string()],
+            starts_at: None,
+            ends_at: VOTING_ENDS,
+            action: Some(ProposalAction::ApproveSale { buyer: account(BUYER), split_price: U128(60_000), duration_ms: DURATION })
+        });
+        contract.vote(proposal_id, 0);
+
+        set_context_at(OWNER, 0, VOTING_ENDS);
+        contract.finalize_proposal(proposal_id);
+
+        set_context_at(OWNER, 0, EXECUTED);
+        contract.execute_proposal(proposal_id);
+
+        approve(&mut contract, "1", OWNER);
+        approve(&mut contract, "2", OWNER);
+
+        contract
+    }
+
+    fn sale_payment(price: Balance) -> SalePayment {
+        SalePayment {
+            seller: account(OWNER),
+            buyer: account(BUYER),
+            currency: None,
+            price: U128(price),
+            fee: U128(price * 250 / 10000),
+            deposit: U128(price)
+        }
+    }
+
+    #[test]
+    fn tendered_splits_are_paid_from_the_buyers_escrow() {
+        let mut contract = approved_sale();
+
+        set_context_at(BUYER, 120_000, EXECUTED);
+        assert_eq!(contract.fund_property_sale(U128(1)).0, 120_000);
+
+        set_context_at(OWNER, 0, EXECUTED);
+        contract.tender_split_to_property_sale(U128(1));
+
+        assert_eq!(function_calls(), vec!["nft_transfer_payout", "on_transfer_token_callback_on_sale"]);
+
+        set_context_at(MARKET, 0, EXECUTED);
+        contract.on_transfer_token_callback_on_sale(U128(1), None, sale_payment(60_000), Ok(payout(&[(OWNER, 58_500)])));
+
+        let sale = contract.get_property_sale(U128(1)).unwrap();
+        assert_eq!(sale.escrow, 60_000);
+        assert_eq!(sale.splits_sold, 1);
+        assert!(sale.pending_split_ids.is_empty());
+        assert_eq!(contract.property_splits.get(0).unwrap().owner, account(BUYER));
+        assert_eq!(transfers()[OWNER], 58_500);
+    }
+
+    #[test]
+    fn failed_tender_refunds_the_buyer_and_frees_the_split() {
+        let mut contract = approved_sale();
+
+        set_context_at(BUYER, 60_000, EXECUTED);
+        contract.fund_property_sale(U128(1));
+
+        set_context_at(OWNER, 0, EXECUTED);
+        contract.tender_split_to_property_sale(U128(2));
+
+        set_context_at(MARKET, 0, EXECUTED);
+        contract.on_transfer_token_callback_on_sale(U128(2), None, sale_payment(60_000), Err(PromiseError::Failed));
+
+        let sale = contract.get_property_sale(U128(1)).unwrap();
+        assert_eq!(transfers()[BUYER], 60_000);
+        assert_eq!(sale.escrow, 0);
+        assert_eq!(sale.splits_sold, 0);
+        assert!(!contract.is_split_locked(&U128(2)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Buyer has not paid for this split")]
+    fn split_cannot_be_tendered_before_the_buyer_pays() {
+        let mut contract = approved_sale();
+
+        set_context_at(OWNER, 0, EXECUTED);
+        contract.tender_split_to_property_sale(U128(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Deposit exceeds the price of the splits left to buy")]
+    fn buyer_cannot_pay_in_more_than_the_property_costs() {
+        let mut contract = approved_sale();
+
+        set_context_at(BUYER, 120_001, EXECUTED);
+        contract.fund_property_sale(U128(1));
+    }
+
+    #[test]
+    fn buyer_withdraws_untendered_escrow_after_the_sale_closes() {
+        let mut contract = approved_sale();
+
+        set_context_at(BUYER, 120_000, EXECUTED);
+        contract.fund_property_sale(U128(1));
+
+        set_context_at(BUYER, 0, EXECUTED + DURATION);
+        assert_eq!(contract.withdraw_property_sale_escrow(U128(1)).0, 120_000);
+        assert_eq!(transfers()[BUYER], 120_000);
+        assert_eq!(contract.get_property_sale(U128(1)).unwrap().escrow, 0);
+    }
+}
@@ -0,0 +1,289 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, log, near_bindgen, AccountId, Balance, require};
+use std::collections::HashMap;
+
+use crate::{PropertyArgs, RietsAfrica, RietsAfricaExt};
+
+// each split minted costs an `nft_mint` call and its callback, so a successful raise mints its splits
+// over as many `finalize_raise` calls as it needs
+pub const MAX_RAISE_MINT_PAGE: u64 = 5;
+
+#[derive(Clone, PartialEq, BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub enum RaiseStatus {
+    Open,
+    Succeeded,
+    Failed
+}
+
+#[derive(Clone, BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Pledge {
+    investor: AccountId,
+    splits: u64
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RaiseArgs {
+    pub name: String,
+    pub image_url: String,
+    pub identifier: String,
+    pub doc_urls: Vec<String>,
+    pub royalty: Option<HashMap<AccountId, u32>>,
+    pub split_price: U128,
+    pub soft_cap: U128,
+    pub deadline: u64
+}
+
+#[derive(Clone, BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Raise {
+    id: U128,
+    name: String,
+    image: String,
+    property_identifier: String,
+    doc_urls: Vec<String>,
+    royalty: Option<HashMap<AccountId, u32>>,
+    split_price: Balance,
+    // the raise only goes ahead if at least this much is pledged by the deadline
+    soft_cap: Balance,
+    // block timestamp in milliseconds
+    deadline: u64,
+    pledged: Balance,
+    pledges: Vec<Pledge>,
+    status: RaiseStatus,
+    property_id: Option<U128>,
+    splits_minted: u64
+}
+
+impl Raise {
+    pub fn splits_pledged(&self) -> u64 {
+        self.pledges.iter().map(|pledge| pledge.splits).sum()
+    }
+
+    fn property_args(&self) -> PropertyArgs {
+        PropertyArgs {
+            name: self.name.clone(),
+            image_url: self.image.clone(),
+            identifier: self.property_identifier.clone(),
+            valuation: U128::from(self.split_price * self.doc_urls.len() as u128),
+            valuation_currency: None,
+            doc_urls: self.doc_urls.clone(),
+            royalty: self.royalty.clone()
+        }
+    }
+}
+
+
+#[near_bindgen]
+impl RietsAfrica {
+
+    // opens an all-or-nothing raise for an off-plan property with one split per document
+    pub fn create_raise(&mut self, args: RaiseArgs) -> U128 {
+
+        let RaiseArgs { name, image_url, identifier, doc_urls, royalty, split_price, soft_cap, deadline } = args;
+
+        require!(env::signer_account_id() == self.owner, "Not authorised");
+        require!(!doc_urls.is_empty(), "A raise needs at least one split");
+        require!(split_price.0 > 0, "Split price must be greater than zero");
+        require!(soft_cap.0 <= split_price.0 * doc_urls.len() as u128, "Soft cap is above what the splits can raise");
+        require!(deadline > env::block_timestamp_ms(), "Deadline must be in the future");
+
+        let raise_id = U128::from(u128::from(self.raises.len()) + 1);
+
+        let raise = Raise {
+            id: raise_id,
+            name,
+            image: image_url,
+            property_identifier: identifier,
+            doc_urls,
+            royalty,
+            split_price: split_price.0,
+            soft_cap: soft_cap.0,
+            deadline,
+            pledged: 0,
+            pledges: Vec::new(),
+            status: RaiseStatus::Open,
+            property_id: None,
+            splits_minted: 0
+        };
+
+        self.raises.push(&raise);
+
+        raise_id
+    }
+
+    // pledges for `splits` splits; the deposit must match their price exactly
+    #[payable]
+    pub fn pledge_to_raise(&mut self, raise_id: U128, splits: u64) {
+
+        let mut raise = self.get_raise(raise_id);
+
+        let investor = env::signer_account_id();
+
+        require!(raise.status == RaiseStatus::Open && env::block_timestamp_ms() < raise.deadline, "Raise is not open");
+        require!(investor != self.owner, "Not authorized");
+        require!(splits > 0, "Pledge at least one split");
+        require!(raise.splits_pledged() + splits <= raise.doc_urls.len() as u64, "Not enough splits left in this raise");
+        require!(env::attached_deposit() == raise.split_price * u128::from(splits), "Deposit must equal the price of the pledged splits");
+
+        match raise.pledges.iter_mut().find(|pledge| pledge.investor == investor) {
+            Some(pledge) => pledge.splits += splits,
+            None => raise.pledges.push(Pledge { investor, splits })
+        }
+
+        raise.pledged += env::attached_deposit();
+
+        self.raises.replace((raise_id.0 - 1) as u64, &raise);
+    }
+
+    // anyone can finalize a raise after its deadline, or earlier once every split is pledged.
+    // A raise that met its soft cap creates the property and mints up to `limit` of its splits, each
+    // pledged split delivered to its investor; calling again mints the next page until every split is
+    // minted. A raise that missed its soft cap makes every pledge claimable through `claim_refund`
+    pub fn finalize_raise(&mut self, raise_id: U128, limit: Option<u64>) {
+
+        let mut raise = self.get_raise(raise_id);
+
+        if raise.status == RaiseStatus::Open {
+            require!(
+                env::block_timestamp_ms() >= raise.deadline || raise.splits_pledged() == raise.doc_urls.len() as u64,
+                "Raise is still open"
+            );
+
+            if raise.pledged < raise.soft_cap {
+                for pledge in raise.pledges.iter() {
+                    self.credit_refund(&pledge.investor, &None, raise.split_price * u128::from(pledge.splits));
+                }
+
+                raise.status = RaiseStatus::Failed;
+                self.raises.replace((raise_id.0 - 1) as u64, &raise);
+
+                log!("Raise {} missed its soft cap with {} pledged, pledges are refundable", raise_id.0, raise.pledged);
+                return;
+            }
+
+            raise.status = RaiseStatus::Succeeded;
+            raise.property_id = Some(self.internal_create_property(&raise.property_args()));
+        }
+
+        let total = raise.doc_urls.len() as u64;
+
+        require!(raise.status == RaiseStatus::Succeeded && raise.splits_minted < total, "Raise is already finalized");
+
+        let purchasers: Vec<AccountId> = raise.pledges.iter()
+            .flat_map(|pledge| std::iter::repeat_n(pledge.investor.clone(), pledge.splits as usize))
+            .collect();
+
+        let end = total.min(raise.splits_minted + limit.unwrap_or(MAX_RAISE_MINT_PAGE).clamp(1, MAX_RAISE_MINT_PAGE));
+
+        self.mint_property_splits(
+            raise.property_id.unwrap(),
+            &raise.property_args(),
+            raise.splits_minted as usize..end as usize,
+            &purchasers,
+            raise.split_price
+        );
+
+        raise.splits_minted = end;
+
+        self.raises.replace((raise_id.0 - 1) as u64, &raise);
+    }
+
+    pub fn get_raise(&self, raise_id: U128) -> Raise {
+        self.raises.get((raise_id.0 - 1) as u64).unwrap_or_else(|| env::panic_str("Raise id does not exist"))
+    }
+
+    pub fn get_raises(&self) -> Vec<Raise> {
+        self.raises.iter().collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{account, function_calls, set_context, set_context_at, setup, OWNER};
+
+    const DEADLINE: u64 = 1_000;
+
+    // a raise of `splits` splits at 10,000 each that needs half of them pledged
+    fn create_raise(contract: &mut RietsAfrica, splits: usize) -> U128 {
+        set_context(OWNER, 0);
+
+        contract.create_raise(RaiseArgs {
+            name: "Eko Heights".to_string(),
+            image_url: "https://example.com/eko.png".to_string(),
+            identifier: "EKO".to_string(),
+            doc_urls: (1..=splits).map(|split| format!("https://example.com/{}.pdf", split)).collect(),
+            royalty: None,
+            split_price: U128(10_000),
+            soft_cap: U128(10_000 * splits as u128 / 2),
+            deadline: DEADLINE
+        })
+    }
+
+    fn pledge(contract: &mut RietsAfrica, raise_id: U128, investor: &str, splits: u64) {
+        set_context(investor, 10_000 * u128::from(splits));
+        contract.pledge_to_raise(raise_id, splits);
+    }
+
+    #[test]
+    fn raise_below_its_soft_cap_refunds_every_pledge() {
+        let mut contract = setup();
+        let raise_id = create_raise(&mut contract, 4);
+
+        pledge(&mut contract, raise_id, "alice.testnet", 1);
+
+        set_context_at("anyone.testnet", 0, DEADLINE);
+        contract.finalize_raise(raise_id, None);
+
+        let raise = contract.get_raise(raise_id);
+        assert!(raise.status == RaiseStatus::Failed);
+        assert_eq!(raise.property_id, None);
+        assert_eq!(contract.get_claimable_refund(account("alice.testnet"), None).0, 10_000);
+        assert!(function_calls().is_empty());
+    }
+
+    #[test]
+    fn successful_raise_mints_its_splits_a_page_at_a_time() {
+        let mut contract = setup();
+        let raise_id = create_raise(&mut contract, 12);
+
+        pledge(&mut contract, raise_id, "alice.testnet", 4);
+        pledge(&mut contract, raise_id, "bob.testnet", 3);
+
+        set_context_at("anyone.testnet", 0, DEADLINE);
+        contract.finalize_raise(raise_id, None);
+
+        let raise = contract.get_raise(raise_id);
+        assert!(raise.status == RaiseStatus::Succeeded);
+        assert_eq!(raise.property_id, Some(U128(2)));
+        assert_eq!(raise.splits_minted, MAX_RAISE_MINT_PAGE);
+        assert_eq!(function_calls().iter().filter(|call| *call == "nft_mint").count(), MAX_RAISE_MINT_PAGE as usize);
+
+        set_context_at("anyone.testnet", 0, DEADLINE);
+        contract.finalize_raise(raise_id, Some(100));
+        set_context_at("anyone.testnet", 0, DEADLINE);
+        contract.finalize_raise(raise_id, None);
+
+        assert_eq!(contract.get_raise(raise_id).splits_minted, 12);
+        assert_eq!(function_calls().iter().filter(|call| *call == "nft_mint").count(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Raise is already finalized")]
+    fn fully_minted_raise_cannot_be_finalized_again() {
+        let mut contract = setup();
+        let raise_id = create_raise(&mut contract, 2);
+
+        pledge(&mut contract, raise_id, "alice.testnet", 2);
+
+        set_context_at("anyone.testnet", 0, DEADLINE);
+        contract.finalize_raise(raise_id, None);
+        contract.finalize_raise(raise_id, None);
+    }
+}
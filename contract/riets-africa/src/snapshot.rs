@@ -0,0 +1,198 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::{env, log, near_bindgen, AccountId, Balance, Promise, require};
+
+use crate::{PropertyIncome, RietsAfrica, RietsAfricaExt};
+
+
+#[derive(Clone, BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SnapshotHolding {
+    property_split_id: U128,
+    owner: AccountId
+}
+
+// split ownership of a property frozen at its record date; income paid against it goes to these
+// holders whoever owns the splits later
+#[derive(Clone, BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Snapshot {
+    id: U128,
+    pub(crate) property_id: U128,
+    // block timestamp in milliseconds
+    record_date: u64,
+    holdings: Vec<SnapshotHolding>,
+    income: PropertyIncome
+}
+
+impl Snapshot {
+    // splits held by `account` at the record date
+    pub fn splits_of(&self, account: &AccountId) -> Vec<U128> {
+        self.holdings.iter()
+            .filter(|holding| holding.owner == *account)
+            .map(|holding| holding.property_split_id)
+            .collect()
+    }
+}
+
+
+#[near_bindgen]
+impl RietsAfrica {
+
+    // records who holds each split of the property right now
+    pub fn take_snapshot(&mut self, property_id: U128) -> U128 {
+
+        self.require_property_manager(&property_id);
+
+        let property = self.properties.get((property_id.0 - 1) as u64).unwrap();
+
+        require!(!property.split_ids.is_empty(), "Property has no splits to snapshot");
+
+        let snapshot_id = U128::from(u128::from(self.snapshots.len()) + 1);
+
+        let holdings = property.split_ids.iter()
+            .map(|split_id| {
+                let property_split = self.property_splits.get((split_id.0 - 1) as u64).unwrap();
+
+                SnapshotHolding { property_split_id: *split_id, owner: property_split.owner }
+            })
+            .collect();
+
+        let snapshot = Snapshot {
+            id: snapshot_id,
+            property_id,
+            record_date: env::block_timestamp_ms(),
+            holdings,
+            income: PropertyIncome::default()
+        };
+
+        self.snapshots.push(&snapshot);
+
+        let mut property_snapshots = self.property_snapshots.get(&property_id).unwrap_or_default();
+        property_snapshots.push(snapshot_id);
+        self.property_snapshots.insert(&property_id, &property_snapshots);
+
+        log!("Snapshot {} of property {} recorded", snapshot_id.0, property_id.0);
+
+        snapshot_id
+    }
+
+    // shares the attached deposit equally between the splits of the snapshot
+    #[payable]
+    pub fn deposit_snapshot_income(&mut self, snapshot_id: U128) {
+
+        let snapshot = self.get_snapshot(snapshot_id);
+
+        self.require_property_manager(&snapshot.property_id);
+        require!(env::attached_deposit() > 0, "Income must be greater than zero");
+
+        self.internal_distribute_snapshot_income(&snapshot_id, env::attached_deposit());
+
+        log!("Income of {} deposited for snapshot {}", env::attached_deposit(), snapshot_id.0);
+    }
+
+    // claims what the caller's splits at the record date have earned from the snapshot
+    pub fn claim_snapshot_income(&mut self, snapshot_id: U128) -> U128 {
+
+        let mut snapshot = self.get_snapshot(snapshot_id);
+
+        let account = env::signer_account_id();
+
+        let mut amount = 0;
+
+        for property_split_id in snapshot.splits_of(&account) {
+            let key = (snapshot_id, property_split_id);
+            let claimed = self.snapshot_income_claimed.get(&key).unwrap_or(0);
+            let unclaimed = snapshot.income.per_split() - claimed;
+
+            if unclaimed > 0 {
+                self.snapshot_income_claimed.insert(&key, &(claimed + unclaimed));
+                amount += unclaimed;
+            }
+        }
+
+        require!(amount > 0, "No income to claim");
+
+        snapshot.income.record_claim(amount);
+        self.snapshots.replace((snapshot_id.0 - 1) as u64, &snapshot);
+
+        Promise::new(account.clone()).transfer(amount);
+
+        log!("Income of {} from snapshot {} claimed by {}", amount, snapshot_id.0, account);
+
+        U128::from(amount)
+    }
+
+    pub fn get_snapshot(&self, snapshot_id: U128) -> Snapshot {
+        self.snapshots.get((snapshot_id.0 - 1) as u64).unwrap_or_else(|| env::panic_str("Snapshot id does not exist"))
+    }
+
+    pub fn get_property_snapshots(&self, property_id: U128) -> Vec<U128> {
+        self.property_snapshots.get(&property_id).unwrap_or_default()
+    }
+
+    pub fn get_unclaimed_snapshot_income(&self, snapshot_id: U128, account_id: AccountId) -> U128 {
+        let snapshot = self.get_snapshot(snapshot_id);
+
+        U128::from(
+            snapshot.splits_of(&account_id).into_iter()
+                .map(|property_split_id| snapshot.income.per_split() - self.snapshot_income_claimed.get(&(snapshot_id, property_split_id)).unwrap_or(0))
+                .sum::<u128>()
+        )
+    }
+
+    pub(crate) fn internal_distribute_snapshot_income(&mut self, snapshot_id: &U128, amount: Balance) {
+        let mut snapshot = self.get_snapshot(*snapshot_id);
+
+        snapshot.income.distribute(snapshot.holdings.len() as u128, amount);
+
+        self.snapshots.replace((snapshot_id.0 - 1) as u64, &snapshot);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{account, payment, payout, set_context, setup, transfers, BUYER, MARKET, OWNER};
+
+    #[test]
+    fn snapshot_income_goes_to_the_holders_at_the_record_date_after_a_sale() {
+        let mut contract = setup();
+
+        set_context(OWNER, 0);
+        let snapshot_id = contract.take_snapshot(U128(1));
+
+        set_context(MARKET, 0);
+        contract.on_transfer_token_callback_on_sale(U128(1), None, payment(BUYER, 50_000, 50_000), Ok(payout(&[(OWNER, 48_750)])));
+
+        set_context(OWNER, 100_000);
+        contract.deposit_snapshot_income(snapshot_id);
+
+        assert_eq!(contract.get_unclaimed_snapshot_income(snapshot_id, account(BUYER)).0, 0);
+
+        set_context(OWNER, 0);
+        assert_eq!(contract.claim_snapshot_income(snapshot_id).0, 100_000);
+        assert_eq!(transfers()[OWNER], 100_000);
+        assert_eq!(contract.get_unclaimed_snapshot_income(snapshot_id, account(OWNER)).0, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "No income to claim")]
+    fn buyer_after_the_record_date_has_no_snapshot_income() {
+        let mut contract = setup();
+
+        set_context(OWNER, 0);
+        let snapshot_id = contract.take_snapshot(U128(1));
+
+        set_context(MARKET, 0);
+        contract.on_transfer_token_callback_on_sale(U128(1), None, payment(BUYER, 50_000, 50_000), Ok(payout(&[(OWNER, 48_750)])));
+
+        set_context(OWNER, 100_000);
+        contract.deposit_snapshot_income(snapshot_id);
+
+        set_context(BUYER, 0);
+        contract.claim_snapshot_income(snapshot_id);
+    }
+}
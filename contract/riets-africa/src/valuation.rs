@@ -0,0 +1,97 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::{env, log, near_bindgen, AccountId, Balance, require};
+
+use crate::{Property, RietsAfrica, RietsAfricaExt};
+
+pub const DEFAULT_MAX_RATE_AGE_MS: u64 = 60 * 60 * 1000;
+
+
+#[derive(Clone, BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ExchangeRate {
+    currency: String,
+    // yoctoNEAR per minor unit of the currency, e.g. per kobo or cent
+    yocto_per_unit: Balance,
+    // block timestamp in milliseconds
+    updated_at: u64
+}
+
+impl ExchangeRate {
+    pub fn is_stale(&self, max_age_ms: u64) -> bool {
+        env::block_timestamp_ms() > self.updated_at.saturating_add(max_age_ms)
+    }
+}
+
+// ISO 4217 style codes such as NGN or USD
+pub(crate) fn require_currency_code(currency: &str) {
+    require!(currency.len() == 3 && currency.chars().all(|c| c.is_ascii_uppercase()), "Currency must be a three letter code");
+}
+
+
+#[near_bindgen]
+impl RietsAfrica {
+
+    pub fn set_rate_feeder(&mut self, rate_feeder: Option<AccountId>) {
+        require!(env::signer_account_id() == self.owner, "Not authorised");
+
+        self.rate_feeder = rate_feeder;
+    }
+
+    // rates older than this are refused when pricing splits
+    pub fn set_max_rate_age(&mut self, max_age_ms: u64) {
+        require!(env::signer_account_id() == self.owner, "Not authorised");
+        require!(max_age_ms > 0, "Max rate age must be greater than zero");
+
+        self.max_rate_age_ms = max_age_ms;
+    }
+
+    pub fn push_exchange_rate(&mut self, currency: String, yocto_per_unit: U128) {
+        require!(Some(env::predecessor_account_id()) == self.rate_feeder, "Not authorised");
+        require_currency_code(&currency);
+        require!(yocto_per_unit.0 > 0, "Rate must be greater than zero");
+
+        let rate = ExchangeRate {
+            currency: currency.clone(),
+            yocto_per_unit: yocto_per_unit.0,
+            updated_at: env::block_timestamp_ms()
+        };
+
+        self.exchange_rates.insert(&currency, &rate);
+
+        log!("Rate for {} set to {} yoctoNEAR", currency, yocto_per_unit.0);
+    }
+
+    pub fn get_exchange_rate(&self, currency: String) -> Option<ExchangeRate> {
+        self.exchange_rates.get(&currency)
+    }
+
+    pub fn get_rate_feeder(&self) -> Option<AccountId> {
+        self.rate_feeder.clone()
+    }
+
+    pub fn get_max_rate_age(&self) -> u64 {
+        self.max_rate_age_ms
+    }
+
+    pub fn get_property_valuation_in_near(&self, property_id: U128) -> U128 {
+        let property = self.properties.get((property_id.0 - 1) as u64).unwrap_or_else(|| env::panic_str("Property id does not exist"));
+
+        U128::from(self.valuation_in_near(&property))
+    }
+
+    // a property's valuation in yoctoNEAR at the latest fresh rate for its currency
+    pub(crate) fn valuation_in_near(&self, property: &Property) -> Balance {
+        let currency = match &property.valuation_currency {
+            Some(currency) => currency,
+            None => return property.valuation
+        };
+
+        let rate = self.exchange_rates.get(currency).unwrap_or_else(|| env::panic_str("No exchange rate for the valuation currency"));
+
+        require!(!rate.is_stale(self.max_rate_age_ms), "Exchange rate is stale");
+
+        property.valuation * rate.yocto_per_unit
+    }
+}
@@ -0,0 +1,387 @@
+// Token purchases and offers paid through `ft_transfer_call` on the mock fungible token. Both
+// contracts run in the same mocked blockchain; every cross-contract receipt the test needs is
+// delivered by hand, the way the runtime would.
+use mock_ft::Contract as MockFt;
+use near_contract_standards::fungible_token::core::FungibleTokenCore;
+use near_contract_standards::fungible_token::resolver::FungibleTokenResolver;
+use near_contract_standards::non_fungible_token::metadata::TokenMetadata;
+use near_contract_standards::non_fungible_token::Token;
+use near_contract_standards::storage_management::StorageManagement;
+use near_sdk::json_types::U128;
+use near_sdk::mock::{Receipt, VmAction};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json::{self, json, Value};
+use near_sdk::test_utils::{get_created_receipts, VMContextBuilder};
+use near_sdk::{testing_env, AccountId, Balance, PromiseError, PromiseOrValue, PromiseResult, RuntimeFeesConfig, VMConfig};
+use riets_africa::{PropertyArgs, RietsAfrica, SalePayment, NFT_CONTRACT};
+
+const MARKET: &str = "market.testnet";
+const OWNER: &str = "owner.testnet";
+const TOKEN: &str = "usdt.testnet";
+const BUYER: &str = "alice.testnet";
+const BIDDER: &str = "bob.testnet";
+const ARTIST: &str = "artist.testnet";
+
+// the mock token has 6 decimals, like USDT
+const USDT: Balance = 1_000_000;
+const NEAR: Balance = 10u128.pow(24);
+
+const START_BALANCE: Balance = 1_000_000 * USDT;
+const STORAGE_DEPOSIT: Balance = 10u128.pow(22);
+
+// 250 basis points of every sale go to the treasury
+const FEE_BPS: u16 = 250;
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct SaleCallbackArgs {
+    property_split_id: U128,
+    offer_id: Option<U128>,
+    payment: SalePayment
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct FtTransferArgs {
+    receiver_id: AccountId,
+    amount: U128,
+    memo: Option<String>
+}
+
+fn account(id: &str) -> AccountId {
+    id.parse().unwrap()
+}
+
+fn set_context(current: &str, signer: &str, predecessor: &str, deposit: Balance) {
+    testing_env!(VMContextBuilder::new()
+        .current_account_id(account(current))
+        .signer_account_id(account(signer))
+        .predecessor_account_id(account(predecessor))
+        .attached_deposit(deposit)
+        .build());
+}
+
+struct Setup {
+    market: RietsAfrica,
+    ft: MockFt
+}
+
+impl Setup {
+    // a property of two splits valued at 50,000 NEAR each, both still held by the issuer
+    fn new() -> Self {
+        set_context(MARKET, OWNER, OWNER, 0);
+
+        let mut market = RietsAfrica::default();
+        market.add_accepted_token(account(TOKEN));
+        market.set_platform_fee(FEE_BPS);
+        market.create_property(PropertyArgs {
+            name: "Lekki Gardens".to_string(),
+            image_url: "https://example.com/lekki.png".to_string(),
+            identifier: "LKG".to_string(),
+            valuation: U128(100_000 * NEAR),
+            valuation_currency: None,
+            doc_urls: vec!["https://example.com/1.pdf".to_string(), "https://example.com/2.pdf".to_string()],
+            royalty: None
+        });
+
+        set_context(MARKET, MARKET, MARKET, 0);
+
+        for token_id in ["1", "2"] {
+            let token = Token {
+                token_id: token_id.to_string(),
+                owner_id: account(OWNER),
+                metadata: Some(empty_metadata()),
+                approved_account_ids: None
+            };
+
+            market.on_mint_nft_callback(U128(1), format!("LKG000{}", token_id), None, U128(0), Ok(token));
+        }
+
+        set_context(TOKEN, OWNER, OWNER, 0);
+
+        let mut ft = MockFt::new("USDT".to_string(), 6);
+
+        for holder in [MARKET, OWNER, BUYER, BIDDER, ARTIST] {
+            set_context(TOKEN, holder, holder, STORAGE_DEPOSIT);
+            ft.storage_deposit(None, None);
+        }
+
+        ft.mint(account(BUYER), U128(START_BALANCE));
+        ft.mint(account(BIDDER), U128(START_BALANCE));
+
+        Self { market, ft }
+    }
+
+    fn list(&mut self, token_id: &str, price: Balance) {
+        set_context(MARKET, OWNER, NFT_CONTRACT, 0);
+
+        let msg = json!({ "price": U128(price), "currency": TOKEN }).to_string();
+
+        self.market.nft_on_approve(token_id.to_string(), account(OWNER), 1, msg);
+    }
+
+    // lets the marketplace move the split's token without listing it
+    fn approve(&mut self, token_id: &str) {
+        set_context(MARKET, OWNER, NFT_CONTRACT, 0);
+
+        self.market.nft_on_approve(token_id.to_string(), account(OWNER), 1, String::new());
+    }
+
+    // runs `ft_transfer_call` from `sender` to the marketplace and resolves it on the token, returning
+    // the receipts the marketplace created along the way
+    fn transfer_call(&mut self, sender: &str, amount: Balance, msg: Value) -> Vec<Receipt> {
+        let msg = msg.to_string();
+
+        set_context(TOKEN, sender, sender, 1);
+        let _ = self.ft.ft_transfer_call(account(MARKET), U128(amount), None, msg.clone());
+
+        set_context(MARKET, sender, TOKEN, 0);
+        let unused = match self.market.ft_on_transfer(account(sender), U128(amount), msg) {
+            PromiseOrValue::Value(unused) => unused,
+            PromiseOrValue::Promise(_) => panic!("ft_on_transfer should answer with a value")
+        };
+        let receipts = get_created_receipts();
+
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(account(TOKEN))
+                .predecessor_account_id(account(TOKEN))
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(serde_json::to_vec(&unused).unwrap())]
+        );
+        self.ft.ft_resolve_transfer(account(sender), account(MARKET), U128(amount));
+
+        receipts
+    }
+
+    // answers the split transfer scheduled in `receipts` with `payout` from the token contract, or
+    // with a failure, and delivers the token payments that follow
+    fn settle(&mut self, receipts: Vec<Receipt>, payout: Option<Value>) {
+        let args: SaleCallbackArgs = serde_json::from_slice(&function_call_args(&receipts, "on_transfer_token_callback_on_sale")).unwrap();
+
+        let result = match payout {
+            Some(payout) => Ok(serde_json::from_value(json!({ "payout": payout })).unwrap()),
+            None => Err(PromiseError::Failed)
+        };
+
+        set_context(MARKET, MARKET, MARKET, 0);
+        self.market.on_transfer_token_callback_on_sale(args.property_split_id, args.offer_id, args.payment, result);
+
+        self.deliver(get_created_receipts());
+    }
+
+    // applies every `ft_transfer` the marketplace sent to the token
+    fn deliver(&mut self, receipts: Vec<Receipt>) {
+        for receipt in receipts.iter().filter(|receipt| receipt.receiver_id.as_str() == TOKEN) {
+            for action in receipt.actions.iter() {
+                if let VmAction::FunctionCall { function_name, args, .. } = action {
+                    if function_name == "ft_transfer" {
+                        let transfer: FtTransferArgs = serde_json::from_slice(args).unwrap();
+
+                        set_context(TOKEN, MARKET, MARKET, 1);
+                        self.ft.ft_transfer(transfer.receiver_id, transfer.amount, transfer.memo);
+                    }
+                }
+            }
+        }
+    }
+
+    fn balance(&self, holder: &str) -> Balance {
+        self.ft.ft_balance_of(account(holder)).0
+    }
+
+    fn token_treasury(&self) -> Value {
+        to_json(&self.market.get_token_treasury(account(TOKEN)))
+    }
+
+    fn claimable(&self, holder: &str) -> Balance {
+        self.market.get_claimable_refund(account(holder), Some(account(TOKEN))).0
+    }
+}
+
+fn empty_metadata() -> TokenMetadata {
+    TokenMetadata {
+        title: None,
+        description: None,
+        media: None,
+        media_hash: None,
+        copies: None,
+        issued_at: None,
+        expires_at: None,
+        starts_at: None,
+        updated_at: None,
+        extra: None,
+        reference: None,
+        reference_hash: None
+    }
+}
+
+// views hold balances as u128, which only go through JSON text
+fn to_json<T: Serialize>(value: &T) -> Value {
+    serde_json::from_str(&serde_json::to_string(value).unwrap()).unwrap()
+}
+
+fn amount(value: &Value) -> Balance {
+    value.as_u64().unwrap().into()
+}
+
+fn function_call_args(receipts: &[Receipt], name: &str) -> Vec<u8> {
+    receipts.iter()
+        .flat_map(|receipt| receipt.actions.iter())
+        .find_map(|action| match action {
+            VmAction::FunctionCall { function_name, args, .. } if function_name == name => Some(args.clone()),
+            _ => None
+        })
+        .unwrap_or_else(|| panic!("no {} receipt", name))
+}
+
+
+#[test]
+fn buy_pays_seller_and_royalties_and_returns_what_was_paid_above_the_price() {
+    let mut setup = Setup::new();
+    setup.list("1", 100_000 * USDT);
+
+    let receipts = setup.transfer_call(BUYER, 120_000 * USDT, json!({ "action": "buy", "property_split_id": "1" }));
+
+    // the 20,000 USDT above the price goes straight back through ft_resolve_transfer
+    assert_eq!(setup.balance(BUYER), START_BALANCE - 100_000 * USDT);
+    assert_eq!(setup.balance(MARKET), 100_000 * USDT);
+
+    // the token contract reports the price less the fee, split with a 10% royalty
+    setup.settle(receipts, Some(json!({ ARTIST: U128(9_750 * USDT), OWNER: U128(87_750 * USDT) })));
+
+    assert_eq!(setup.balance(OWNER), 87_750 * USDT);
+    assert_eq!(setup.balance(ARTIST), 9_750 * USDT);
+    assert_eq!(setup.balance(MARKET), 2_500 * USDT);
+    assert_eq!(amount(&setup.token_treasury()["balance"]), 2_500 * USDT);
+    assert_eq!(amount(&setup.token_treasury()["fees_collected"]), 2_500 * USDT);
+
+    let sales = to_json(&setup.market.get_split_sales(U128(1)));
+    assert_eq!(sales[0]["buyer"], BUYER);
+    assert_eq!(sales[0]["currency"], TOKEN);
+}
+
+#[test]
+fn failed_split_transfer_returns_the_full_token_deposit() {
+    let mut setup = Setup::new();
+    setup.list("1", 100_000 * USDT);
+
+    let receipts = setup.transfer_call(BUYER, 100_000 * USDT, json!({ "action": "buy", "property_split_id": "1" }));
+    setup.settle(receipts, None);
+
+    assert_eq!(setup.balance(BUYER), START_BALANCE);
+    assert_eq!(setup.balance(MARKET), 0);
+    assert_eq!(amount(&setup.token_treasury()["balance"]), 0);
+}
+
+#[test]
+#[should_panic(expected = "Property is priced in another currency")]
+fn buy_in_a_token_the_split_is_not_priced_in_fails() {
+    let mut setup = Setup::new();
+
+    set_context(MARKET, OWNER, NFT_CONTRACT, 0);
+    setup.market.nft_on_approve("1".to_string(), account(OWNER), 1, json!({ "price": U128(100_000 * USDT) }).to_string());
+
+    setup.transfer_call(BUYER, 100_000 * USDT, json!({ "action": "buy", "property_split_id": "1" }));
+}
+
+#[test]
+fn offer_holds_the_tokens_until_the_seller_accepts() {
+    let mut setup = Setup::new();
+
+    setup.transfer_call(BIDDER, 60_000 * USDT, json!({ "action": "offer", "property_split_id": "1" }));
+
+    assert_eq!(setup.balance(BIDDER), START_BALANCE - 60_000 * USDT);
+    assert_eq!(setup.balance(MARKET), 60_000 * USDT);
+
+    let offers = to_json(&setup.market.get_split_offers(U128(1)));
+    assert_eq!(amount(&offers[0]["value"]), 60_000 * USDT);
+    assert_eq!(offers[0]["currency"], TOKEN);
+
+    setup.approve("1");
+
+    set_context(MARKET, OWNER, OWNER, 0);
+    setup.market.sell_property_to_offer(U128(1), U128(1));
+
+    setup.settle(get_created_receipts(), Some(json!({ OWNER: U128(58_500 * USDT) })));
+
+    assert_eq!(setup.balance(OWNER), 58_500 * USDT);
+    assert_eq!(setup.balance(MARKET), 1_500 * USDT);
+    assert_eq!(amount(&setup.token_treasury()["balance"]), 1_500 * USDT);
+}
+
+#[test]
+#[should_panic(expected = "Not sufficient deposit to make offer")]
+fn token_offer_below_the_listing_price_in_that_token_is_refused() {
+    let mut setup = Setup::new();
+    setup.list("1", 100_000 * USDT);
+
+    setup.transfer_call(BIDDER, 10_000 * USDT, json!({ "action": "offer", "property_split_id": "1" }));
+}
+
+#[test]
+fn accepting_a_counter_offer_tops_up_the_deposit_and_refunds_the_excess() {
+    let mut setup = Setup::new();
+
+    setup.transfer_call(BIDDER, 60_000 * USDT, json!({ "action": "offer", "property_split_id": "1" }));
+
+    setup.approve("1");
+
+    set_context(MARKET, OWNER, OWNER, 0);
+    setup.market.counter_offer(U128(1), U128(1), U128(80_000 * USDT));
+
+    let receipts = setup.transfer_call(BIDDER, 30_000 * USDT, json!({ "action": "accept_counter_offer", "property_split_id": "1", "offer_id": "1" }));
+
+    assert_eq!(setup.balance(MARKET), 90_000 * USDT);
+
+    setup.settle(receipts, Some(json!({ OWNER: U128(78_000 * USDT) })));
+
+    // 90,000 USDT deposited against a price of 80,000 USDT
+    assert_eq!(setup.balance(BIDDER), START_BALANCE - 80_000 * USDT);
+    assert_eq!(setup.balance(OWNER), 78_000 * USDT);
+    assert_eq!(setup.balance(MARKET), 2_000 * USDT);
+    assert_eq!(amount(&setup.token_treasury()["balance"]), 2_000 * USDT);
+}
+
+#[test]
+fn open_offers_become_claimable_when_the_split_is_sold() {
+    let mut setup = Setup::new();
+
+    setup.transfer_call(BIDDER, 60_000 * USDT, json!({ "action": "offer", "property_split_id": "1" }));
+
+    setup.list("1", 100_000 * USDT);
+
+    let receipts = setup.transfer_call(BUYER, 100_000 * USDT, json!({ "action": "buy", "property_split_id": "1" }));
+    setup.settle(receipts, Some(json!({ OWNER: U128(97_500 * USDT) })));
+
+    assert_eq!(setup.claimable(BIDDER), 60_000 * USDT);
+
+    set_context(MARKET, BIDDER, BIDDER, 0);
+    setup.market.claim_refund(Some(account(TOKEN)));
+    setup.deliver(get_created_receipts());
+
+    assert_eq!(setup.claimable(BIDDER), 0);
+    assert_eq!(setup.balance(BIDDER), START_BALANCE);
+}
+
+#[test]
+fn failed_token_payments_are_credited_back() {
+    let mut setup = Setup::new();
+
+    set_context(MARKET, MARKET, MARKET, 0);
+    setup.market.on_payment_resolved(account(TOKEN), account(BIDDER), U128(500 * USDT), false, Err(PromiseError::Failed));
+
+    assert_eq!(setup.claimable(BIDDER), 500 * USDT);
+
+    setup.market.on_payment_resolved(account(TOKEN), account(OWNER), U128(700 * USDT), true, Err(PromiseError::Failed));
+
+    assert_eq!(amount(&setup.token_treasury()["balance"]), 700 * USDT);
+    assert_eq!(setup.claimable(OWNER), 0);
+
+    setup.market.on_payment_resolved(account(TOKEN), account(BIDDER), U128(500 * USDT), false, Ok(()));
+
+    assert_eq!(setup.claimable(BIDDER), 500 * USDT);
+}